@@ -0,0 +1,77 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+Page {
+    id: sessionsPage
+
+    // JSON array as returned by greeter.get_charging_sessions(), newest first
+    property var sessions: []
+    property var vehicleUnits: ({speed: "km/h"})
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Charging sessions')
+    }
+
+    ListModel {
+        id: sessions_model
+    }
+
+    function fill() {
+        sessions_model.clear();
+        for (var i = 0; i < sessions.length; i++) {
+            var s = sessions[i];
+            var start = new Date(s.start * 1000);
+            var minutes = Math.round((s.end - s.start) / 60);
+            sessions_model.append({
+                title: start.toLocaleString(Qt.locale(), Locale.ShortFormat) + "  " + minutes + " min"
+                    + (s.charger_type !== "" ? "  " + s.charger_type : ""),
+                summary: s.start_soc + "% → " + s.end_soc + "%  " + s.energy_added.toFixed(1) + "kWh"
+                    + "  peak " + s.peak_charge_rate.toFixed(0) + vehicleUnits.speed,
+                gps_pos: s.gps_pos
+            });
+        }
+    }
+
+    onSessionsChanged: fill()
+
+    ListView {
+        anchors {
+            margins: units.gu(1)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: sessions_model
+
+        delegate: ItemDelegate {
+            width: parent.width
+            text: model.title + "\n" + model.summary
+            onClicked: {
+                if (model.gps_pos !== "") {
+                    Qt.openUrlExternally('geo:' + model.gps_pos);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+Page {
+    id: sitesPage
+
+    // JSON array as returned by greeter.get_charging_sites(), sorted by distance
+    property var sites: []
+    property bool superchargersOnly: false
+    property var vehicleUnits: ({distance: "km"})
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Charging sites')
+    }
+
+    ListModel {
+        id: sites_model
+    }
+
+    function fill() {
+        sites_model.clear();
+        for (var i = 0; i < sites.length; i++) {
+            if (!superchargersOnly || sites[i].supercharger) {
+                sites_model.append(sites[i]);
+            }
+        }
+    }
+
+    onSitesChanged: fill()
+    onSuperchargersOnlyChanged: fill()
+
+    ColumnLayout {
+        spacing: units.gu(1)
+        anchors {
+            margins: units.gu(1)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+
+        CheckBox {
+            id: chk_superchargers
+            text: i18n.tr('Superchargers only')
+            onClicked: {
+                superchargersOnly = chk_superchargers.checked;
+            }
+        }
+
+        ListView {
+            Layout.fillWidth: true
+            Layout.fillHeight: true
+            clip: true
+            model: sites_model
+
+            delegate: ItemDelegate {
+                width: parent.width
+                text: (model.supercharger
+                        ? "⚡ " + model.name + "  " + model.available_stalls + "/" + model.total_stalls
+                        : model.name)
+                      + "  " + model.distance.toFixed(1) + vehicleUnits.distance
+                onClicked: {
+                    Qt.openUrlExternally('geo:' + model.gps_pos);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+Page {
+    id: historyPage
+
+    property var greeter
+    property int vehicleIndex: 0
+    property var vehicleUnits: ({distance: "km", temperature: "°C"})
+    property int hours: 24
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('History')
+    }
+
+    ListModel {
+        id: history_model
+    }
+
+    // greeter.get_history() returns the snapshots oldest first
+    function fill() {
+        history_model.clear();
+        var points = JSON.parse(greeter.get_history(vehicleIndex, hours));
+        for (var i = points.length - 1; i >= 0; i--) {
+            var p = points[i];
+            history_model.append({
+                time: new Date(p.timestamp * 1000).toLocaleString(Qt.locale(), Locale.ShortFormat),
+                summary: p.battery_level + "%  " + p.battery_range.toFixed(0) + vehicleUnits.distance
+                    + "  " + (p.outside_temp === null ? "" : p.outside_temp.toFixed(1) + vehicleUnits.temperature)
+                    + "  " + p.charging_state,
+                gps_pos: p.gps_pos
+            });
+        }
+    }
+
+    onHoursChanged: fill()
+    Component.onCompleted: fill()
+
+    ColumnLayout {
+        spacing: units.gu(1)
+        anchors {
+            margins: units.gu(1)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+
+        ComboBox {
+            id: cmb_period
+            model: [i18n.tr('Last day'), i18n.tr('Last week'), i18n.tr('Last month')]
+            onActivated: (i) => {
+                hours = [24, 24 * 7, 24 * 30][i];
+            }
+        }
+
+        ListView {
+            Layout.fillWidth: true
+            Layout.fillHeight: true
+            clip: true
+            model: history_model
+
+            delegate: ItemDelegate {
+                width: parent.width
+                text: model.time + "  " + model.summary
+                onClicked: {
+                    if (model.gps_pos !== "") {
+                        Qt.openUrlExternally('geo:' + model.gps_pos);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+Page {
+    id: lockPage
+    objectName: "lockPage"
+
+    property var greeter: null
+    // called after the PIN was accepted
+    property var onUnlocked: null
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('uttesla is locked')
+    }
+
+    ColumnLayout {
+        spacing: units.gu(1)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        TextField {
+            id: txt_app_pin
+            Layout.fillWidth: true
+            placeholderText: i18n.tr('App PIN')
+            echoMode: TextInput.Password
+            focus: true
+            onAccepted: btn_unlock_app.clicked()
+        }
+
+        Button {
+            id: btn_unlock_app
+            Layout.fillWidth: true
+            text: i18n.tr('Unlock')
+            onClicked: {
+                var err = greeter.unlock_app(txt_app_pin.text);
+                txt_app_pin.text = "";
+                if (err === "") {
+                    lbl_lock_err.text = "";
+                    pageStack.pop();
+                    if (onUnlocked) {
+                        onUnlocked();
+                    }
+                } else {
+                    lbl_lock_err.text = err;
+                }
+            }
+        }
+
+        Label {
+            id: lbl_lock_err
+            Layout.fillWidth: true
+            wrapMode: Text.WordWrap
+            color: "red"
+        }
+    }
+}
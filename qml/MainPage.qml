@@ -18,8 +18,8 @@ import QtQuick 2.7
 import QtQuick.Controls 2.2
 import Ubuntu.Components 1.3
 import QtQuick.Layouts 1.3
-import Qt.labs.settings 1.0
 import QtQml 2.12
+import Ubuntu.Content 1.3
 
 import Greeter 1.0
 
@@ -31,13 +31,50 @@ Page {
 
     Greeter {
         id: greeter
+        sentry_on_lock: appSettings.sentry_on_lock
+        distance_unit: appSettings.distance_unit
+        temperature_unit: appSettings.temperature_unit
+        pressure_unit: appSettings.pressure_unit
+        range_type: appSettings.range_type
+        tpms_front_min: appSettings.tpms_front_min
+        tpms_rear_min: appSettings.tpms_rear_min
+        preferred_temp: appSettings.hvac_temp
+        preferred_charge_limit: appSettings.charge_limit
+        auto_relock_minutes: appSettings.auto_relock
+        history_days: appSettings.history_days
     }
 
+    Connections {
+        target: ContentHub
+        onShareRequested: {
+            if (transfer.items.length > 0) {
+                var item = transfer.items[0];
+                txt_destination.text = item.text ? item.text : item.url.toString();
+            }
+        }
+    }
+
+
+    // unit labels of the last vehicle data, "units" is taken by Ubuntu.Components
+    property var vehicleUnits: ({distance: "km", speed: "km/h", temperature: "°C", pressure: "bar", temp_min: 15, temp_max: 28})
+
     anchors.fill: parent
 
+    property bool readOnly: false
+
     header: PageHeader {
         id: header
-        title: i18n.tr('Tesla')
+        title: readOnly ? i18n.tr('Tesla (read-only)') : i18n.tr('Tesla')
+
+        trailingActionBar.actions: [
+            Action {
+                iconName: "settings"
+                text: i18n.tr('Settings')
+                onTriggered: {
+                    pageStack.push(Qt.resolvedUrl("SettingsPage.qml"), {greeter: greeter, vehicleIndex: vehicle.currentIndex});
+                }
+            }
+        ]
     }
 
     ColumnLayout {
@@ -72,25 +109,78 @@ Page {
             ComboBox {
                 id: vehicle
                 onActivated: (i) => {
+                    var vin = greeter.vin(i);
+                    if (vin !== "") {
+                        appSettings.vehicle_vin = vin;
+                    }
                     try {
 		        var vehicle_data = JSON.parse(greeter.get_vehicle_data(i));
-		        lbl_state.text = vehicle_data.state;
+		        lbl_state.text = vehicle_data.stale
+		            ? vehicle_data.state + " " + i18n.tr('(offline, %1 old)').arg(vehicle_data.age)
+		            : vehicle_data.state;
 		        txt_pos.text = vehicle_data.gps_pos;
-		        lbl_temp.text = "Temperature Out:" + vehicle_data.outside_temp + " In: " + vehicle_data.inside_temp;
+		        vehicleUnits = vehicle_data.units;
+		        lbl_temp.text = "Temperature Out:" + vehicle_data.outside_temp + vehicleUnits.temperature + " In: " + vehicle_data.inside_temp + vehicleUnits.temperature;
 		        spb_temp.value = vehicle_data.driver_temp_setting;
 			chk_hvac.checked = vehicle_data.hvac_enabled;
-			lbl_batt.text = "Battery: " + vehicle_data.battery_level + "%  " + vehicle_data.battery_range.toFixed(1) + "km";
-			lbl_charge.text = "Charge rate: " + vehicle_data.charge_rate + "  minutes remaining: " + vehicle_data.minutes_to_full_charge + "  added: " + vehicle_data.charge_energy_added.toFixed(1) + "kWh";
+			lbl_batt.text = "Battery: " + vehicle_data.battery_level + "%  " + vehicle_data.battery_range.toFixed(1) + vehicleUnits.distance + " " + vehicle_data.range_type;
+			lbl_charge.text = "Charge rate: " + vehicle_data.charge_rate.toFixed(0) + vehicleUnits.speed + "  minutes remaining: " + vehicle_data.minutes_to_full_charge + "  added: " + vehicle_data.charge_energy_added.toFixed(1) + "kWh";
 			spb_chg_limit.value = vehicle_data.charge_limit;
+			lbl_windows.text = "Windows FD: " + (vehicle_data.windows.front_driver ? "open" : "closed")
+			    + " FP: " + (vehicle_data.windows.front_passenger ? "open" : "closed")
+			    + " RD: " + (vehicle_data.windows.rear_driver ? "open" : "closed")
+			    + " RP: " + (vehicle_data.windows.rear_passenger ? "open" : "closed");
+			lbl_locked.text = vehicle_data.locked ? "locked" : "unlocked";
+			chk_sentry.checked = vehicle_data.sentry_mode;
+			chk_valet.checked = vehicle_data.valet_mode;
+			chk_speed_limit.checked = vehicle_data.speed_limit_active;
+			spb_speed_limit.value = Math.round(vehicle_data.speed_limit);
+			btn_clear_pin.enabled = vehicle_data.speed_limit_pin_set;
+			chk_pin_drive.checked = vehicle_data.pin_to_drive === true;
+			chk_pin_drive.text = vehicle_data.pin_to_drive === null ? i18n.tr('PIN to drive (unknown)') : i18n.tr('PIN to drive');
+			chk_guest.checked = vehicle_data.guest_mode === true;
+			chk_guest.text = vehicle_data.guest_mode === null ? i18n.tr('Guest (unknown)') : i18n.tr('Guest');
+			btn_homelink.enabled = vehicle_data.homelink_nearby;
+			lbl_media.text = vehicle_data.now_playing
+			    ? vehicle_data.now_playing.artist + " - " + vehicle_data.now_playing.title + " (" + vehicle_data.now_playing.source + ")"
+			    : i18n.tr('Nothing playing');
+			var update = vehicle_data.software_update;
+			lbl_update.text = update
+			    ? "Update " + update.version + ": " + update.status + " " + update.download_percent + "%  ~" + Math.round(update.expected_duration_sec / 60) + "min"
+			    : i18n.tr('No update pending');
+			btn_update_schedule.enabled = update !== null;
+			btn_update_cancel.enabled = update !== null;
+			var tires = vehicle_data.tires;
+			var fmt = (p) => p === null ? "?" : p.toFixed(1);
+			lbl_tires.text = tires
+			    ? "Tires " + tires.unit + " FL: " + fmt(tires.front_left) + " FR: " + fmt(tires.front_right)
+			        + " RL: " + fmt(tires.rear_left) + " RR: " + fmt(tires.rear_right)
+			        + (tires.hard_warning ? " ⚠⚠" : (tires.soft_warning ? " ⚠" : ""))
+			    : i18n.tr('Tire pressure unavailable');
 		    } catch(err) {
 		        lbl_state.text = "unavailable";
 		        txt_pos.text = "unavailable";
 		        lbl_temp.text = "Temperature unavailable";
-		        spb_temp.value = 22;
+		        vehicleUnits = JSON.parse(greeter.unit_labels(i));
+		        spb_temp.value = Math.round(greeter.user_temperature(i, appSettings.hvac_temp));
 			chk_hvac.checked = false;
 			lbl_batt.text = "Battery state unavailable";
 			lbl_charge.text = "Charge state unavailable";
-			spb_chg_limit.value = 80;
+			spb_chg_limit.value = appSettings.charge_limit;
+			lbl_windows.text = "Windows unavailable";
+			lbl_locked.text = "unavailable";
+			chk_sentry.checked = false;
+			chk_valet.checked = false;
+			chk_speed_limit.checked = false;
+			btn_clear_pin.enabled = false;
+			chk_pin_drive.checked = false;
+			chk_guest.checked = false;
+			btn_homelink.enabled = false;
+			lbl_media.text = i18n.tr('Media unavailable');
+			lbl_update.text = i18n.tr('Update state unavailable');
+			btn_update_schedule.enabled = false;
+			btn_update_cancel.enabled = false;
+			lbl_tires.text = i18n.tr('Tire pressure unavailable');
 		    }
                 }
             }
@@ -99,6 +189,26 @@ Page {
                 id: lbl_state
                 text: i18n.tr('state')
             }
+
+            Button {
+                id: btn_details
+                text: i18n.tr('About')
+                onClicked: {
+                    try {
+                        var details = JSON.parse(greeter.get_vehicle_details(vehicle.currentIndex));
+                        pageStack.push(Qt.resolvedUrl("VehicleDetails.qml"), {details: details, vehicleUnits: vehicleUnits});
+                    } catch(err) {
+                    }
+                }
+            }
+
+            Button {
+                id: btn_history
+                text: i18n.tr('History')
+                onClicked: {
+                    pageStack.push(Qt.resolvedUrl("HistoryPage.qml"), {greeter: greeter, vehicleIndex: vehicle.currentIndex, vehicleUnits: vehicleUnits});
+                }
+            }
         }
 
 	// position
@@ -125,6 +235,36 @@ Page {
                     Qt.openUrlExternally(url);
                 }
             }
+
+            Button {
+                id: btn_chargers
+                text: i18n.tr('Chargers')
+                onClicked: {
+                    try {
+                        var sites = JSON.parse(greeter.get_charging_sites(vehicle.currentIndex));
+                        pageStack.push(Qt.resolvedUrl("ChargingSites.qml"), {sites: sites, vehicleUnits: vehicleUnits});
+                    } catch(err) {
+                    }
+                }
+            }
+        }
+
+        RowLayout {
+            spacing: units.gu(1)
+
+            TextField {
+                id: txt_destination
+                placeholderText: i18n.tr('Address or lat,lon')
+                implicitWidth: units.gu(20)
+            }
+
+            Button {
+                id: btn_destination
+                text: i18n.tr('Navigate')
+                onClicked: {
+                    greeter.share_destination(vehicle.currentIndex, txt_destination.text);
+                }
+            }
         }
 
 	// temperature
@@ -154,9 +294,10 @@ Page {
 
             SpinBox {
                 id: spb_temp
-                value: 20
-                from: 10
-                to: 30
+                // the preference is stored in °C
+                value: Math.round(greeter.user_temperature(vehicle.currentIndex, appSettings.hvac_temp))
+                from: vehicleUnits.temp_min
+                to: vehicleUnits.temp_max
                 implicitWidth: units.gu(9)
             }
         }
@@ -196,13 +337,25 @@ Page {
                 }
             }
 
+            Button {
+                id: btn_charge_sessions
+                text: i18n.tr('Sessions')
+                onClicked: {
+                    try {
+                        var sessions = JSON.parse(greeter.get_charging_sessions(vehicle.currentIndex));
+                        pageStack.push(Qt.resolvedUrl("ChargingSessions.qml"), {sessions: sessions, vehicleUnits: vehicleUnits});
+                    } catch(err) {
+                    }
+                }
+            }
+
             Label {
                 id: lbl_chg_limit
                 text: i18n.tr('to %')
             }
             SpinBox {
                 id: spb_chg_limit
-                value: 80
+                value: appSettings.charge_limit
                 from: 50
                 to: 100
                 stepSize: 5
@@ -210,6 +363,16 @@ Page {
             }
         }
 
+	// tires
+        RowLayout {
+            spacing: units.gu(1)
+
+            Label {
+                id: lbl_tires
+                text: i18n.tr('Tires')
+            }
+	}
+
 	// alerts
         RowLayout {
             spacing: units.gu(1)
@@ -239,7 +402,7 @@ Page {
                 id: btn_unlock
                 text: i18n.tr('Unlock')
                 onClicked: {
-                    greeter.doors(vehicle.currentIndex, true);
+                    guarded("unlock", i18n.tr('Unlock the doors?'), (token) => greeter.doors(vehicle.currentIndex, true, token));
                 }
             }
 
@@ -247,7 +410,37 @@ Page {
                 id: btn_lock
                 text: i18n.tr('Lock')
                 onClicked: {
-                    greeter.doors(vehicle.currentIndex, false);
+                    greeter.doors(vehicle.currentIndex, false, "");
+                }
+            }
+
+            Label {
+                id: lbl_locked
+                text: i18n.tr('locked')
+            }
+
+            Button {
+                id: btn_homelink
+                text: i18n.tr('HomeLink')
+                enabled: false
+                onClicked: {
+                    greeter.trigger_homelink(vehicle.currentIndex);
+                }
+            }
+
+            Button {
+                id: btn_trunk
+                text: i18n.tr('Trunk')
+                onClicked: {
+                    guarded("open_trunk", i18n.tr('Open the trunk?'), (token) => greeter.trunk(vehicle.currentIndex, false, token));
+                }
+            }
+
+            Button {
+                id: btn_frunk
+                text: i18n.tr('Frunk')
+                onClicked: {
+                    guarded("open_frunk", i18n.tr('Open the frunk?'), (token) => greeter.trunk(vehicle.currentIndex, true, token));
                 }
             }
 
@@ -255,7 +448,244 @@ Page {
                 id: btn_drive
                 text: i18n.tr('Drive')
                 onClicked: {
-                    greeter.drive(vehicle.currentIndex);
+                    guarded("remote_start", i18n.tr('Allow keyless driving?'), (token) => greeter.drive(vehicle.currentIndex, token));
+                }
+            }
+        }
+        RowLayout {
+            spacing: units.gu(1)
+
+            CheckBox {
+                id: chk_pin_drive
+                text: i18n.tr('PIN to drive')
+                onClicked: {
+                    greeter.pin_drive(vehicle.currentIndex, chk_pin_drive.checked, txt_pin.text);
+                }
+            }
+
+            CheckBox {
+                id: chk_guest
+                text: i18n.tr('Guest')
+                onClicked: {
+                    greeter.guest(vehicle.currentIndex, chk_guest.checked);
+                }
+            }
+        }
+
+	// sentry
+        RowLayout {
+            spacing: units.gu(1)
+
+            CheckBox {
+                id: chk_sentry
+                text: i18n.tr('Sentry')
+                onClicked: {
+                    if (chk_sentry.checked) {
+                        greeter.sentry(vehicle.currentIndex, true, "");
+                    } else {
+                        guarded("disable_sentry", i18n.tr('Disable sentry mode?'), (token) => greeter.sentry(vehicle.currentIndex, false, token));
+                    }
+                }
+            }
+        }
+
+	// valet and speed limit
+        RowLayout {
+            spacing: units.gu(1)
+
+            TextField {
+                id: txt_pin
+                placeholderText: i18n.tr('PIN')
+                echoMode: TextInput.Password
+                inputMethodHints: Qt.ImhDigitsOnly
+                maximumLength: 4
+                implicitWidth: units.gu(8)
+            }
+
+            CheckBox {
+                id: chk_valet
+                text: i18n.tr('Valet')
+                onClicked: {
+                    greeter.valet(vehicle.currentIndex, chk_valet.checked, txt_pin.text);
+                }
+            }
+
+            CheckBox {
+                id: chk_speed_limit
+                text: i18n.tr('Speed limit')
+                onClicked: {
+                    greeter.speed_limit(vehicle.currentIndex, chk_speed_limit.checked, txt_pin.text);
+                }
+            }
+        }
+        RowLayout {
+            spacing: units.gu(1)
+
+            SpinBox {
+                id: spb_speed_limit
+                value: 120
+                from: vehicleUnits.speed === "mph" ? 50 : 80
+                to: vehicleUnits.speed === "mph" ? 90 : 145
+                stepSize: 5
+                implicitWidth: units.gu(10)
+            }
+
+            Button {
+                id: btn_speed_limit
+                text: i18n.tr('Set limit')
+                onClicked: {
+                    greeter.set_speed_limit(vehicle.currentIndex, spb_speed_limit.value);
+                }
+            }
+
+            Button {
+                id: btn_clear_pin
+                text: i18n.tr('Clear PIN')
+                onClicked: {
+                    greeter.clear_speed_limit_pin(vehicle.currentIndex);
+                }
+            }
+        }
+
+	// media
+        RowLayout {
+            spacing: units.gu(1)
+
+            Label {
+                id: lbl_media
+                text: i18n.tr('Media')
+            }
+	}
+        RowLayout {
+            spacing: units.gu(1)
+
+            Button {
+                text: '⏮'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.media_prev_track(vehicle.currentIndex)
+            }
+            Button {
+                text: '⏯'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.media_toggle(vehicle.currentIndex)
+            }
+            Button {
+                text: '⏭'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.media_next_track(vehicle.currentIndex)
+            }
+            Button {
+                text: '★-'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.media_prev_fav(vehicle.currentIndex)
+            }
+            Button {
+                text: '★+'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.media_next_fav(vehicle.currentIndex)
+            }
+            Button {
+                text: '🔉'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.volume_down(vehicle.currentIndex)
+            }
+            Button {
+                text: '🔊'
+                implicitWidth: units.gu(4)
+                onClicked: greeter.volume_up(vehicle.currentIndex)
+            }
+        }
+
+	// windows
+        RowLayout {
+            spacing: units.gu(1)
+
+            Label {
+                id: lbl_windows
+                text: i18n.tr('Windows')
+            }
+	}
+        RowLayout {
+            spacing: units.gu(1)
+
+            Button {
+                id: btn_vent
+                text: i18n.tr('Vent')
+                onClicked: {
+                    greeter.windows(vehicle.currentIndex, true);
+                }
+            }
+
+            Button {
+                id: btn_close
+                text: i18n.tr('Close')
+                onClicked: {
+                    greeter.windows(vehicle.currentIndex, false);
+                }
+            }
+        }
+
+	// software update
+        RowLayout {
+            spacing: units.gu(1)
+
+            Label {
+                id: lbl_update
+                text: i18n.tr('Software update')
+            }
+	}
+        RowLayout {
+            spacing: units.gu(1)
+
+            Label {
+                text: i18n.tr('in min')
+            }
+            SpinBox {
+                id: spb_update_offset
+                value: 0
+                from: 0
+                to: 720
+                stepSize: 30
+                implicitWidth: units.gu(10)
+            }
+
+            Button {
+                id: btn_update_schedule
+                text: i18n.tr('Install')
+                enabled: false
+                onClicked: {
+                    greeter.schedule_update(vehicle.currentIndex, spb_update_offset.value);
+                }
+            }
+
+            Button {
+                id: btn_update_cancel
+                text: i18n.tr('Cancel')
+                enabled: false
+                onClicked: {
+                    greeter.cancel_update(vehicle.currentIndex);
+                }
+            }
+        }
+
+	// commands waiting for the car to come back online
+        Repeater {
+            id: outbox
+            model: []
+
+            delegate: RowLayout {
+                spacing: units.gu(1)
+
+                Label {
+                    text: i18n.tr('Pending: %1 (%2 min left)').arg(modelData.description).arg(Math.ceil(modelData.expires_in_sec / 60))
+                }
+
+                Button {
+                    text: i18n.tr('Cancel')
+                    onClicked: {
+                        greeter.cancel_command(modelData.id);
+                        outbox.model = JSON.parse(greeter.pending_commands());
+                    }
                 }
             }
         }
@@ -274,12 +704,24 @@ Page {
 
             onTriggered: {
                 eventlog.text = greeter.update_log();
+                outbox.model = JSON.parse(greeter.pending_commands());
+            }
+        }
+
+        Timer {
+            id: relock_timer;
+            interval: 30000;
+            running: appSettings.auto_relock > 0;
+            repeat: true
+
+            onTriggered: {
+                greeter.check_relock();
             }
         }
 
         Timer {
             id: refresh_timer;
-            interval: 10000;
+            interval: appSettings.refresh_interval * 1000;
             running: true;
             repeat: true
 
@@ -290,9 +732,135 @@ Page {
 
     }
 
-    Component.onCompleted: {
+    // Sensitive commands only run with a token from the greeter, which we ask the user for
+    function guarded(action, question, run) {
+        if (greeter.confirm_dangerous() && greeter.confirm_with_pin() && greeter.app_lock_enabled()) {
+            var idx = vehicle.currentIndex;
+            pin_dialog.question = question;
+            pin_dialog.run = (pin) => {
+                var token = greeter.request_confirmation_pin(action, idx, pin);
+                if (token !== "") {
+                    run(token);
+                }
+            };
+            pin_dialog.open();
+            return;
+        }
+        var token = greeter.request_confirmation(action, vehicle.currentIndex);
+        if (token === "") {
+            run("");
+            return;
+        }
+        confirm_dialog.question = question;
+        confirm_dialog.run = () => run(token);
+        confirm_dialog.open();
+    }
+
+    Dialog {
+        id: confirm_dialog
+        property string question: ""
+        property var run: null
+
+        anchors.centerIn: parent
+        modal: true
+        title: i18n.tr('Please confirm')
+        standardButtons: Dialog.Yes | Dialog.No
+
+        Label {
+            text: confirm_dialog.question
+        }
+
+        onAccepted: {
+            if (run) {
+                run();
+            }
+            run = null;
+        }
+        onRejected: {
+            run = null;
+        }
+    }
+
+    Dialog {
+        id: pin_dialog
+        property string question: ""
+        property var run: null
+
+        anchors.centerIn: parent
+        modal: true
+        title: question
+        standardButtons: Dialog.Ok | Dialog.Cancel
+
+        TextField {
+            id: txt_confirm_pin
+            placeholderText: i18n.tr('App PIN')
+            echoMode: TextInput.Password
+        }
+
+        onAccepted: {
+            if (run) {
+                run(txt_confirm_pin.text);
+            }
+            run = null;
+            txt_confirm_pin.text = "";
+        }
+        onRejected: {
+            run = null;
+            txt_confirm_pin.text = "";
+        }
+    }
+
+    function start() {
+        readOnly = greeter.read_only();
         var vehicles = greeter.login().split("\n");
         vehicle.model = vehicles;
-	vehicle.activated(0);
+        var idx = greeter.index_of_vin(appSettings.vehicle_vin);
+        vehicleUnits = JSON.parse(greeter.unit_labels(idx));
+        vehicle.currentIndex = idx;
+        vehicle.activated(idx);
+    }
+
+    function showLock() {
+        refresh_timer.running = false;
+        pageStack.push(Qt.resolvedUrl("LockPage.qml"), {greeter: greeter, onUnlocked: () => {
+            start();
+            refresh_timer.running = true;
+        }});
+    }
+
+    // lock again when the app was in the background for too long
+    property double inactiveSince: 0
+    Connections {
+        target: Qt.application
+        onStateChanged: {
+            if (Qt.application.state !== Qt.ApplicationActive) {
+                if (inactiveSince === 0) {
+                    inactiveSince = Date.now();
+                }
+            } else if (inactiveSince !== 0) {
+                var idle = Date.now() - inactiveSince;
+                inactiveSince = 0;
+                if (greeter.app_lock_enabled() && !greeter.app_locked()
+                        && idle > appSettings.lock_timeout * 60 * 1000) {
+                    greeter.lock_app();
+                    showLock();
+                }
+            }
+        }
+    }
+
+    // the read-only mode might have been changed in the settings
+    onVisibleChanged: {
+        if (visible) {
+            readOnly = greeter.read_only();
+        }
+    }
+
+    Component.onCompleted: {
+        if (greeter.app_locked()) {
+            showLock();
+        } else {
+            start();
+        }
     }
 }
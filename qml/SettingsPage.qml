@@ -0,0 +1,309 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+// appSettings lives in uttesla.qml and persists every change right away
+Page {
+    id: settingsPage
+
+    property var greeter: null
+    // the thresholds are shown in the units of this vehicle
+    property int vehicleIndex: -1
+    property var unitLabels: ({temperature: "°C", pressure: "bar", temp_min: 15, temp_max: 28})
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Settings')
+    }
+
+    Flickable {
+        anchors {
+            margins: units.gu(1)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        contentHeight: settings_grid.height
+        clip: true
+
+        GridLayout {
+            id: settings_grid
+            width: parent.width
+            columns: 2
+            columnSpacing: units.gu(1)
+            rowSpacing: units.gu(1)
+
+            Label {
+                text: i18n.tr('Distance')
+            }
+            ComboBox {
+                model: ["auto", "km", "mi"]
+                currentIndex: Math.max(0, model.indexOf(appSettings.distance_unit))
+                onActivated: (i) => {
+                    appSettings.distance_unit = model[i];
+                }
+            }
+
+            Label {
+                text: i18n.tr('Temperature')
+            }
+            ComboBox {
+                model: ["auto", "C", "F"]
+                currentIndex: Math.max(0, model.indexOf(appSettings.temperature_unit))
+                onActivated: (i) => {
+                    appSettings.temperature_unit = model[i];
+                    updateUnits();
+                }
+            }
+
+            Label {
+                text: i18n.tr('Pressure')
+            }
+            ComboBox {
+                model: ["auto", "bar", "psi", "kPa"]
+                currentIndex: Math.max(0, model.indexOf(appSettings.pressure_unit))
+                onActivated: (i) => {
+                    appSettings.pressure_unit = model[i];
+                    updateUnits();
+                }
+            }
+
+            Label {
+                text: i18n.tr('Range')
+            }
+            ComboBox {
+                model: ["auto", "rated", "ideal", "estimated"]
+                currentIndex: Math.max(0, model.indexOf(appSettings.range_type))
+                onActivated: (i) => {
+                    appSettings.range_type = model[i];
+                }
+            }
+
+            Label {
+                text: i18n.tr('Refresh every s')
+            }
+            SpinBox {
+                value: appSettings.refresh_interval
+                from: 5
+                to: 600
+                stepSize: 5
+                onValueModified: {
+                    appSettings.refresh_interval = value;
+                }
+            }
+
+            Label {
+                text: i18n.tr('Preferred %1').arg(unitLabels.temperature)
+            }
+            SpinBox {
+                id: spb_preferred_temp
+                from: unitLabels.temp_min
+                to: unitLabels.temp_max
+                onValueModified: {
+                    appSettings.hvac_temp = greeter.to_celsius(vehicleIndex, value);
+                }
+            }
+
+            Label {
+                text: i18n.tr('Charge limit %')
+            }
+            SpinBox {
+                value: appSettings.charge_limit
+                from: 50
+                to: 100
+                stepSize: 5
+                onValueModified: {
+                    appSettings.charge_limit = value;
+                }
+            }
+
+            Label {
+                text: i18n.tr('Min tire front %1').arg(unitLabels.pressure)
+            }
+            SpinBox {
+                // tenths of the pressure unit
+                id: spb_tpms_front
+                from: 0
+                stepSize: unitLabels.pressure === "bar" ? 1 : 10
+                onValueModified: {
+                    appSettings.tpms_front_min = greeter.to_bar(vehicleIndex, value / 10);
+                }
+                textFromValue: (v) => (v / 10).toFixed(1)
+            }
+
+            Label {
+                text: i18n.tr('Min tire rear %1').arg(unitLabels.pressure)
+            }
+            SpinBox {
+                id: spb_tpms_rear
+                from: 0
+                stepSize: unitLabels.pressure === "bar" ? 1 : 10
+                onValueModified: {
+                    appSettings.tpms_rear_min = greeter.to_bar(vehicleIndex, value / 10);
+                }
+                textFromValue: (v) => (v / 10).toFixed(1)
+            }
+
+            Label {
+                text: i18n.tr('Lock again after min (only while the app is open)')
+            }
+            SpinBox {
+                // 0 disables locking the car again when nobody got in.
+                // It runs in a Timer, which stops while the app is suspended.
+                value: appSettings.auto_relock
+                from: 0
+                to: 60
+                onValueModified: {
+                    appSettings.auto_relock = value;
+                }
+            }
+
+            Label {
+                text: i18n.tr('Keep history days')
+            }
+            SpinBox {
+                // 0 keeps the history forever
+                value: appSettings.history_days
+                from: 0
+                to: 3650
+                stepSize: 30
+                onValueModified: {
+                    appSettings.history_days = value;
+                }
+            }
+
+            CheckBox {
+                Layout.columnSpan: 2
+                text: i18n.tr('Enable sentry mode when locking')
+                checked: appSettings.sentry_on_lock
+                onClicked: {
+                    appSettings.sentry_on_lock = checked;
+                }
+            }
+
+            Label {
+                text: i18n.tr('Lock after min')
+            }
+            SpinBox {
+                value: appSettings.lock_timeout
+                from: 0
+                to: 120
+                onValueModified: {
+                    appSettings.lock_timeout = value;
+                }
+            }
+
+            Label {
+                text: i18n.tr('Current app PIN')
+            }
+            TextField {
+                id: txt_old_app_pin
+                echoMode: TextInput.Password
+            }
+
+            Label {
+                text: i18n.tr('New app PIN')
+            }
+            TextField {
+                id: txt_new_app_pin
+                placeholderText: i18n.tr('empty to remove')
+                echoMode: TextInput.Password
+            }
+
+            Button {
+                text: i18n.tr('Change app PIN')
+                onClicked: {
+                    lbl_pin_result.text = greeter.set_app_pin(txt_old_app_pin.text, txt_new_app_pin.text);
+                    if (lbl_pin_result.text === "") {
+                        lbl_pin_result.text = i18n.tr('App PIN changed');
+                    }
+                    txt_old_app_pin.text = "";
+                    txt_new_app_pin.text = "";
+                }
+            }
+            Label {
+                id: lbl_pin_result
+                Layout.fillWidth: true
+                wrapMode: Text.WordWrap
+            }
+
+            CheckBox {
+                id: chk_read_only
+                Layout.columnSpan: 2
+                text: i18n.tr('Read-only mode on this device (switching it off needs the current app PIN)')
+                checked: greeter.read_only()
+                onClicked: {
+                    var err = greeter.set_read_only(checked, txt_old_app_pin.text);
+                    txt_old_app_pin.text = "";
+                    if (err !== "") {
+                        lbl_pin_result.text = err;
+                    }
+                    checked = greeter.read_only();
+                }
+            }
+
+            CheckBox {
+                id: chk_confirm_dangerous
+                Layout.columnSpan: 2
+                text: i18n.tr('Confirm dangerous actions (switching it off needs the current app PIN)')
+                checked: greeter.confirm_dangerous()
+                onClicked: {
+                    setConfirmation(checked, chk_confirm_with_pin.checked);
+                }
+            }
+
+            CheckBox {
+                id: chk_confirm_with_pin
+                Layout.columnSpan: 2
+                text: i18n.tr('Confirm with the app PIN')
+                enabled: chk_confirm_dangerous.checked
+                checked: greeter.confirm_with_pin()
+                onClicked: {
+                    setConfirmation(chk_confirm_dangerous.checked, checked);
+                }
+            }
+        }
+    }
+
+    // weakening the policy is refused without the current app PIN
+    function setConfirmation(confirmDangerous, withPin) {
+        var err = greeter.set_confirmation(confirmDangerous, withPin, txt_old_app_pin.text);
+        txt_old_app_pin.text = "";
+        if (err !== "") {
+            lbl_pin_result.text = err;
+        }
+        chk_confirm_dangerous.checked = greeter.confirm_dangerous();
+        chk_confirm_with_pin.checked = greeter.confirm_with_pin();
+    }
+
+    // the settings are stored in °C and bar
+    function updateUnits() {
+        unitLabels = JSON.parse(greeter.unit_labels(vehicleIndex));
+        spb_preferred_temp.value = Math.round(greeter.user_temperature(vehicleIndex, appSettings.hvac_temp));
+        var tenths = (bar) => Math.round(greeter.user_pressure(vehicleIndex, bar) * 10);
+        spb_tpms_front.to = tenths(4);
+        spb_tpms_rear.to = tenths(4);
+        spb_tpms_front.value = tenths(appSettings.tpms_front_min);
+        spb_tpms_rear.value = tenths(appSettings.tpms_rear_min);
+    }
+
+    Component.onCompleted: updateUnits()
+}
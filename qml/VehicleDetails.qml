@@ -0,0 +1,85 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+Page {
+    id: detailsPage
+
+    // JSON as returned by greeter.get_vehicle_details()
+    property var details: ({})
+    property var vehicleUnits: ({distance: "km"})
+
+    header: PageHeader {
+        id: header
+        title: details.name ? details.name : i18n.tr('About this car')
+    }
+
+    Flickable {
+        anchors {
+            margins: units.gu(1)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        contentHeight: details_column.height
+        clip: true
+
+        ColumnLayout {
+            id: details_column
+            width: parent.width
+            spacing: units.gu(1)
+
+            Image {
+                Layout.fillWidth: true
+                Layout.preferredHeight: units.gu(20)
+                fillMode: Image.PreserveAspectFit
+                source: details.image_url ? details.image_url : ""
+            }
+
+            Label {
+                text: i18n.tr('Model: ') + details.model + " " + details.trim
+            }
+            Label {
+                text: i18n.tr('VIN: ') + details.vin
+            }
+            Label {
+                text: i18n.tr('Odometer: ') + (details.odometer ? details.odometer.toFixed(0) : "?") + vehicleUnits.distance
+            }
+            Label {
+                text: i18n.tr('Software: ') + details.car_version
+            }
+            Label {
+                text: i18n.tr('Wheels: ') + details.wheel_type
+            }
+            Label {
+                text: i18n.tr('Paint: ') + details.paint_color
+            }
+            Label {
+                text: i18n.tr('Charge port: ') + details.charge_port_type
+            }
+            Label {
+                Layout.fillWidth: true
+                wrapMode: Text.WordWrap
+                text: i18n.tr('Options: ') + (details.options ? details.options.join(", ") : "")
+            }
+        }
+    }
+}
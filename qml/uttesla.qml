@@ -21,6 +21,8 @@ import QtQuick.Controls 2.2
 import QtQuick.Window 2.0
 import "."
 
+import Greeter 1.0
+
 ApplicationWindow {
     id: root
 
@@ -31,6 +33,10 @@ ApplicationWindow {
     title: i18n.tr("uttesla")
     visible: true
 
+    AppSettings {
+        id: appSettings
+    }
+
     Shortcut {
         context: Qt.ApplicationShortcut
         sequence: StandardKey.Quit
@@ -49,6 +55,10 @@ ApplicationWindow {
             pageStack.push(mainPageComponent);
         }
         Keys.onBackPressed: {
+            if (currentItem && currentItem.objectName === "lockPage") {
+                // only the PIN gets past the lock
+                return;
+            }
             if (depth > 1) {
                 pop();
             } else {
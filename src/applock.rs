@@ -0,0 +1,464 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Optional app PIN, stored as an Argon2 hash next to the tokens.
+//! With a PIN set, the token files are encrypted with a key derived from it, so they are
+//! useless without the PIN, even when app_lock.json gets deleted.
+
+use crate::clock::now_sec;
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Wrong entries allowed before the delays kick in
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_DELAY_SEC: u64 = 3600;
+
+pub const ACCESS_TOKEN: &str = "tesla_access_token.txt";
+pub const REFRESH_TOKEN: &str = "tesla_refresh_token.txt";
+/// Marks encrypted token files, the user copies the tokens in as plain text
+const ENCRYPTED: &str = "uttesla-encrypted:";
+const NONCE_LEN: usize = 12;
+
+/// Persisted, so restarting the app doesn't reset the attempt counter
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LockState {
+    hash: String,
+    failed_attempts: u32,
+    blocked_until: u64,
+    /// For the whole installation, as the app only knows one account: a viewer gets
+    /// the app set up on their own device
+    read_only: bool,
+    /// Inverted, so a missing entry means confirming
+    skip_confirmation: bool,
+    confirm_with_pin: bool,
+    /// For deriving the key of the token files, hex encoded
+    key_salt: String,
+}
+
+pub struct AppLock {
+    file: PathBuf,
+    state: LockState,
+    unlocked: bool,
+    /// Only known while unlocked
+    key: Option<Key>,
+}
+
+impl AppLock {
+    pub fn load(file: PathBuf) -> Result<Self, String> {
+        let state = if file.exists() {
+            let json = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read the app lock file {:?}: {}", file, e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| format!("Failed to parse the app lock file {:?}: {}", file, e))?
+        } else {
+            LockState::default()
+        };
+
+        let mut lock = AppLock {
+            file,
+            state,
+            unlocked: false,
+            key: None,
+        };
+        // PINs set before the token files were encrypted
+        if lock.enabled() && lock.state.key_salt.is_empty() {
+            lock.state.key_salt = new_key_salt();
+            lock.save()?;
+        }
+
+        Ok(lock)
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.state.hash.is_empty()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        !self.enabled() || self.unlocked
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = false;
+        self.key = None;
+    }
+
+    pub fn unlock(&mut self, pin: &str) -> Result<(), String> {
+        self.verify(pin)?;
+        let key = self.derive_key(pin)?;
+        // tokens copied in after the PIN was set are still plain text
+        self.rewrite_tokens(Some(&key), Some(&key))?;
+        self.key = Some(key);
+        self.unlocked = true;
+
+        Ok(())
+    }
+
+    /// Checks the pin with rate limiting, also used to re-authenticate sensitive commands
+    pub fn verify(&mut self, pin: &str) -> Result<(), String> {
+        if !self.enabled() {
+            return Err("No app PIN set".to_string());
+        }
+        let now = now_sec();
+        if now < self.state.blocked_until {
+            return Err(format!(
+                "Too many wrong attempts, try again in {} seconds",
+                self.state.blocked_until - now
+            ));
+        }
+        let hash = PasswordHash::new(&self.state.hash)
+            .map_err(|e| format!("Failed to parse the app PIN hash: {}", e))?;
+        if Argon2::default()
+            .verify_password(pin.as_bytes(), &hash)
+            .is_ok()
+        {
+            if self.state.failed_attempts > 0 {
+                self.state.failed_attempts = 0;
+                self.state.blocked_until = 0;
+                self.save()?;
+            }
+            return Ok(());
+        }
+
+        self.state.failed_attempts += 1;
+        if self.state.failed_attempts >= FREE_ATTEMPTS {
+            let exp = (self.state.failed_attempts - FREE_ATTEMPTS).min(10);
+            self.state.blocked_until = now + (30 << exp).min(MAX_DELAY_SEC);
+        }
+        self.save()?;
+
+        Err("Wrong app PIN".to_string())
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.state.read_only
+    }
+
+    /// Only the app PIN can switch a viewer back to full control
+    pub fn set_read_only(&mut self, enable: bool, pin: &str) -> Result<(), String> {
+        if !self.enabled() {
+            return Err("Set an app PIN first to protect the read-only mode".to_string());
+        }
+        if !enable {
+            self.verify(pin)?;
+        }
+        self.state.read_only = enable;
+        self.save()
+    }
+
+    /// Whether sensitive commands need a confirmation at all
+    pub fn confirm_dangerous(&self) -> bool {
+        !self.state.skip_confirmation
+    }
+
+    pub fn confirm_with_pin(&self) -> bool {
+        self.state.confirm_with_pin
+    }
+
+    /// Tightening is always allowed, weakening the policy needs the app PIN if one is set
+    pub fn set_confirmation(
+        &mut self,
+        confirm_dangerous: bool,
+        with_pin: bool,
+        pin: &str,
+    ) -> Result<(), String> {
+        let weaker = (!confirm_dangerous && self.confirm_dangerous())
+            || (!with_pin && self.state.confirm_with_pin);
+        if weaker && self.enabled() {
+            self.verify(pin)?;
+        }
+        self.state.skip_confirmation = !confirm_dangerous;
+        self.state.confirm_with_pin = with_pin;
+        self.save()
+    }
+
+    /// An empty new pin removes the app lock
+    pub fn set_pin(&mut self, old_pin: &str, new_pin: &str) -> Result<(), String> {
+        if self.enabled() {
+            self.verify(old_pin)?;
+        }
+        if new_pin.is_empty() {
+            if self.state.read_only {
+                return Err("Disable the read-only mode before removing the app PIN".to_string());
+            }
+            let old_key = self.old_key(old_pin)?;
+            self.rewrite_tokens(old_key.as_ref(), None)?;
+            self.state = LockState {
+                skip_confirmation: self.state.skip_confirmation,
+                ..Default::default()
+            };
+            self.key = None;
+        } else {
+            if new_pin.chars().count() < 4 {
+                return Err("The app PIN needs at least four characters".to_string());
+            }
+            let old_key = self.old_key(old_pin)?;
+            let salt = SaltString::generate(&mut OsRng);
+            let hash = Argon2::default()
+                .hash_password(new_pin.as_bytes(), &salt)
+                .map_err(|e| format!("Failed to hash the app PIN: {}", e))?
+                .to_string();
+            let key_salt = new_key_salt();
+            let key = derive_key(new_pin, &key_salt)?;
+            self.rewrite_tokens(old_key.as_ref(), Some(&key))?;
+            self.state.hash = hash;
+            self.state.key_salt = key_salt;
+            self.key = Some(key);
+        }
+        self.unlocked = true;
+        self.save()
+    }
+
+    /// None without a PIN set or while locked
+    pub fn read_token(&self, name: &str) -> Result<Option<String>, String> {
+        if !self.is_unlocked() {
+            return Err("The app is locked".to_string());
+        }
+        read_token(&self.token_file(name), self.key.as_ref())
+    }
+
+    pub fn write_token(&self, name: &str, token: &str) -> Result<(), String> {
+        if !self.is_unlocked() {
+            return Err("The app is locked".to_string());
+        }
+        write_token(&self.token_file(name), token, self.key.as_ref())
+    }
+
+    /// Next to the app lock file
+    fn token_file(&self, name: &str) -> PathBuf {
+        self.file.with_file_name(name)
+    }
+
+    /// Before anything changes, so a wrong PIN leaves the files alone
+    fn old_key(&self, old_pin: &str) -> Result<Option<Key>, String> {
+        if self.enabled() {
+            Ok(Some(self.derive_key(old_pin)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn derive_key(&self, pin: &str) -> Result<Key, String> {
+        derive_key(pin, &self.state.key_salt)
+    }
+
+    /// Reads all the token files with the old key and writes them with the new one
+    fn rewrite_tokens(&self, old_key: Option<&Key>, new_key: Option<&Key>) -> Result<(), String> {
+        for name in [ACCESS_TOKEN, REFRESH_TOKEN] {
+            let file = self.token_file(name);
+            if let Some(token) = read_token(&file, old_key)? {
+                write_token(&file, &token, new_key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string(&self.state)
+            .map_err(|e| format!("Failed to serialize the app lock: {:?}", e))?;
+        std::fs::write(&self.file, json)
+            .map_err(|e| format!("Failed to write the app lock file {:?}: {}", self.file, e))
+    }
+}
+
+fn new_key_salt() -> String {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    to_hex(&salt)
+}
+
+fn derive_key(pin: &str, salt: &str) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(pin.as_bytes(), salt.as_bytes(), &mut key)
+        .map_err(|e| format!("Failed to derive the key from the app PIN: {}", e))?;
+
+    Ok(key)
+}
+
+/// Plain text is accepted with any key, it is how the user provides the tokens
+fn read_token(file: &Path, key: Option<&Key>) -> Result<Option<String>, String> {
+    if !file.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| format!("Failed to read the token file {:?}: {}", file, e))?;
+    let content = content.trim();
+    let hex = match content.strip_prefix(ENCRYPTED) {
+        Some(hex) => hex,
+        None => return Ok(Some(content.to_string())),
+    };
+    let key = key.ok_or(format!("The token file {:?} is encrypted", file))?;
+    let data = from_hex(hex).ok_or(format!("The token file {:?} is corrupt", file))?;
+    if data.len() < NONCE_LEN {
+        return Err(format!("The token file {:?} is corrupt", file));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let token = ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| format!("Failed to decrypt the token file {:?}", file))?;
+    let token =
+        String::from_utf8(token).map_err(|_| format!("The token file {:?} is corrupt", file))?;
+
+    Ok(Some(token))
+}
+
+fn write_token(file: &Path, token: &str, key: Option<&Key>) -> Result<(), String> {
+    let content = match key {
+        Some(key) => {
+            let mut nonce = [0u8; NONCE_LEN];
+            OsRng.fill_bytes(&mut nonce);
+            let ciphertext = ChaCha20Poly1305::new(key)
+                .encrypt(Nonce::from_slice(&nonce), token.as_bytes())
+                .map_err(|_| format!("Failed to encrypt the token file {:?}", file))?;
+            format!("{}{}{}", ENCRYPTED, to_hex(&nonce), to_hex(&ciphertext))
+        }
+        None => token.to_string(),
+    };
+    std::fs::write(file, content)
+        .map_err(|e| format!("Failed to write the token file {:?}: {}", file, e))
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    // an odd length fails at the last pair
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_and_rate_limit() {
+        let file = std::env::temp_dir().join("uttesla_test_app_lock.json");
+        let _ = std::fs::remove_file(&file);
+
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert!(lock.is_unlocked());
+        lock.set_pin("", "4711").unwrap();
+        assert!(lock.enabled());
+
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert!(!lock.is_unlocked());
+        assert!(lock.unlock("4711").is_ok());
+        assert!(lock.is_unlocked());
+
+        lock.set_read_only(true, "").unwrap();
+        assert!(lock.set_read_only(false, "0000").is_err());
+        assert!(lock.read_only());
+        lock.set_read_only(false, "4711").unwrap();
+        assert!(!lock.read_only());
+
+        lock.lock();
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(lock.unlock("0000"), Err("Wrong app PIN".to_string()));
+        }
+        // blocked, even with the right pin and after a restart
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert!(lock.unlock("4711").unwrap_err().starts_with("Too many"));
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_confirmation_policy() {
+        let file = std::env::temp_dir().join("uttesla_test_confirmation_policy.json");
+        let _ = std::fs::remove_file(&file);
+
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert!(lock.confirm_dangerous());
+        lock.set_confirmation(false, false, "").unwrap();
+        assert!(!lock.confirm_dangerous());
+
+        lock.set_pin("", "4711").unwrap();
+        lock.set_confirmation(true, true, "").unwrap();
+        assert!(lock.set_confirmation(true, false, "").is_err());
+        assert!(lock.set_confirmation(false, true, "0000").is_err());
+        assert!(lock.confirm_dangerous() && lock.confirm_with_pin());
+
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        lock.set_confirmation(false, false, "4711").unwrap();
+        assert!(!lock.confirm_dangerous());
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_token_encryption() {
+        let dir = std::env::temp_dir().join("uttesla_test_token_encryption");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("app_lock.json");
+        let token_file = dir.join(ACCESS_TOKEN);
+        std::fs::write(&token_file, "secret-token\n").unwrap();
+
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert_eq!(
+            lock.read_token(ACCESS_TOKEN).unwrap(),
+            Some("secret-token".to_string())
+        );
+        lock.set_pin("", "4711").unwrap();
+        let content = std::fs::read_to_string(&token_file).unwrap();
+        assert!(content.starts_with(ENCRYPTED) && !content.contains("secret"));
+
+        // useless without the PIN, even with the app lock file gone
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        assert!(lock.read_token(ACCESS_TOKEN).is_err());
+        lock.unlock("4711").unwrap();
+        assert_eq!(
+            lock.read_token(ACCESS_TOKEN).unwrap(),
+            Some("secret-token".to_string())
+        );
+        assert!(read_token(&token_file, None).is_err());
+
+        lock.write_token(REFRESH_TOKEN, "refresh").unwrap();
+        assert!(lock.set_pin("0000", "1234").is_err());
+        lock.set_pin("4711", "1234").unwrap();
+        let mut lock = AppLock::load(file.clone()).unwrap();
+        lock.unlock("1234").unwrap();
+        assert_eq!(
+            lock.read_token(REFRESH_TOKEN).unwrap(),
+            Some("refresh".to_string())
+        );
+
+        lock.set_pin("1234", "").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&token_file).unwrap(),
+            "secret-token"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
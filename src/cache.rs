@@ -0,0 +1,102 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The last vehicle data that made it from the car, shown while it can't be reached.
+
+use crate::clock::now_sec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    timestamp: u64,
+    data: T,
+}
+
+/// One file per VIN in the app data directory
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache { dir }
+    }
+
+    pub fn store<T: Serialize>(&self, vin: &str, data: &T) -> Result<(), String> {
+        self.store_at(vin, data, now_sec())
+    }
+
+    fn store_at<T: Serialize>(&self, vin: &str, data: &T, timestamp: u64) -> Result<(), String> {
+        let file = self.file(vin);
+        let json = serde_json::to_string(&Entry { timestamp, data })
+            .map_err(|e| format!("Failed to serialize the cached vehicle data: {:?}", e))?;
+        std::fs::write(&file, json)
+            .map_err(|e| format!("Failed to write the vehicle cache {:?}: {}", file, e))
+    }
+
+    /// The data together with its age in seconds
+    pub fn load<T: DeserializeOwned>(&self, vin: &str) -> Result<Option<(T, u64)>, String> {
+        let file = self.file(vin);
+        if !file.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read the vehicle cache {:?}: {}", file, e))?;
+        let entry: Entry<T> = serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse the vehicle cache {:?}: {}", file, e))?;
+
+        Ok(Some((
+            entry.data,
+            now_sec().saturating_sub(entry.timestamp),
+        )))
+    }
+
+    fn file(&self, vin: &str) -> PathBuf {
+        self.dir.join(format!("vehicle_{}.json", vin))
+    }
+}
+
+/// Rough age for the UI, e.g. "5 min"
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{} s", secs),
+        60..=3599 => format!("{} min", secs / 60),
+        3600..=86399 => format!("{} h", secs / 3600),
+        _ => format!("{} d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_load() {
+        let cache = Cache::new(std::env::temp_dir());
+        let vin = "UTTESLATEST0000047";
+        let _ = std::fs::remove_file(cache.file(vin));
+        assert_eq!(cache.load::<Vec<i64>>(vin), Ok(None));
+
+        cache.store_at(vin, &vec![47, 11], now_sec() - 300).unwrap();
+        let (data, age) = cache.load::<Vec<i64>>(vin).unwrap().unwrap();
+        assert_eq!(data, vec![47, 11]);
+        assert!((300..310).contains(&age));
+        assert_eq!(format_age(age), "5 min");
+
+        let _ = std::fs::remove_file(cache.file(vin));
+    }
+}
@@ -0,0 +1,129 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Owner API endpoints that teslatte doesn't wrap (yet).
+//! They reuse the access token of the teslatte OwnerApi.
+
+use crate::retry::Failure;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+use teslatte::{OwnerApi, VehicleId};
+
+const API_URL: &str = "https://owner-api.teslamotors.com/api/1";
+/// A sleeping car can take a while, but a dead connection shouldn't block forever
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// POST /vehicles/{id}/command/{command} and return the "response" member.
+pub async fn post_command(
+    api: &OwnerApi,
+    vid: &VehicleId,
+    command: &str,
+    body: &Value,
+) -> Result<Value, Failure> {
+    let url = format!("{}/vehicles/{}/command/{}", API_URL, vid, command);
+    let resp = client()?
+        .post(&url)
+        .bearer_auth(&api.access_token.0)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| Failure::from_error(&e))?;
+    let response = parse_response(command, resp).await?;
+    check_result(command, &response).map_err(Failure::Fatal)?;
+
+    Ok(response)
+}
+
+/// The car refuses commands with {"result": false, "reason": "..."} and a HTTP 200,
+/// works for our own responses as well as teslatte's PostResponse.
+pub fn check_result<T: Serialize>(command: &str, response: &T) -> Result<(), String> {
+    let json = serde_json::to_value(response)
+        .map_err(|e| format!("Failed to inspect the {} response: {}", command, e))?;
+    match json.get("result").and_then(Value::as_bool) {
+        Some(false) => Err(format!(
+            "{} was refused by the car: {}",
+            command,
+            json.get("reason")
+                .and_then(Value::as_str)
+                .filter(|r| !r.is_empty())
+                .unwrap_or("no reason given")
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// GET /vehicles/{id}/{endpoint} and return the "response" member.
+pub async fn get(api: &OwnerApi, vid: &VehicleId, endpoint: &str) -> Result<Value, Failure> {
+    let url = format!("{}/vehicles/{}/{}", API_URL, vid, endpoint);
+    let resp = client()?
+        .get(&url)
+        .bearer_auth(&api.access_token.0)
+        .send()
+        .await
+        .map_err(|e| Failure::from_error(&e))?;
+    parse_response(endpoint, resp).await
+}
+
+fn client() -> Result<reqwest::Client, Failure> {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| Failure::Fatal(format!("Failed to create the http client: {}", e)))
+}
+
+async fn parse_response(endpoint: &str, resp: reqwest::Response) -> Result<Value, Failure> {
+    let status = resp.status();
+    let retry_after = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let text = resp.text().await.map_err(|e| {
+        Failure::Unknown(format!("Failed to read the {} response: {}", endpoint, e))
+    })?;
+    if !status.is_success() {
+        return Err(Failure::from_status(
+            status.as_u16(),
+            retry_after.as_deref(),
+            format!("{} failed with {}: {}", endpoint, status, text),
+        ));
+    }
+    let json: Value = serde_json::from_str(&text)
+        .map_err(|e| Failure::Fatal(format!("Failed to parse the {} response: {}", endpoint, e)))?;
+
+    Ok(json["response"].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_result() {
+        let accepted = serde_json::json!({"result": true, "reason": ""});
+        assert!(check_result("door_lock", &accepted).is_ok());
+        let refused = serde_json::json!({"result": false, "reason": "vehicle unavailable"});
+        assert_eq!(
+            check_result("door_lock", &refused),
+            Err("door_lock was refused by the car: vehicle unavailable".to_string())
+        );
+        let silent = serde_json::json!({"result": false});
+        assert!(check_result("honk_horn", &silent)
+            .unwrap_err()
+            .ends_with("no reason given"));
+    }
+}
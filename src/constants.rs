@@ -1 +1 @@
-
+pub const MILE_TO_KM: f64 = 1.609344;
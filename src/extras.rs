@@ -0,0 +1,144 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Members of the vehicle data that teslatte doesn't model, read from the raw response.
+//! Only some firmware reports them, so everything is optional.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct VehicleExtras {
+    pub vehicle_state: Option<ExtraVehicleState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ExtraVehicleState {
+    pub pin_to_drive_enabled: Option<bool>,
+    pub guest_mode_enabled: Option<bool>,
+    pub media_info: Option<MediaInfo>,
+    pub software_update: Option<SoftwareUpdate>,
+    /// in bar
+    pub tpms_pressure_fl: Option<f64>,
+    pub tpms_pressure_fr: Option<f64>,
+    pub tpms_pressure_rl: Option<f64>,
+    pub tpms_pressure_rr: Option<f64>,
+    pub tpms_soft_warning_fl: Option<bool>,
+    pub tpms_soft_warning_fr: Option<bool>,
+    pub tpms_soft_warning_rl: Option<bool>,
+    pub tpms_soft_warning_rr: Option<bool>,
+    pub tpms_hard_warning_fl: Option<bool>,
+    pub tpms_hard_warning_fr: Option<bool>,
+    pub tpms_hard_warning_rl: Option<bool>,
+    pub tpms_hard_warning_rr: Option<bool>,
+}
+
+impl ExtraVehicleState {
+    /// Any of the tires
+    pub fn tpms_soft_warning(&self) -> bool {
+        [
+            self.tpms_soft_warning_fl,
+            self.tpms_soft_warning_fr,
+            self.tpms_soft_warning_rl,
+            self.tpms_soft_warning_rr,
+        ]
+        .contains(&Some(true))
+    }
+
+    pub fn tpms_hard_warning(&self) -> bool {
+        [
+            self.tpms_hard_warning_fl,
+            self.tpms_hard_warning_fr,
+            self.tpms_hard_warning_rl,
+            self.tpms_hard_warning_rr,
+        ]
+        .contains(&Some(true))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MediaInfo {
+    pub now_playing_title: Option<String>,
+    pub now_playing_artist: Option<String>,
+    pub now_playing_source: Option<String>,
+    pub media_playback_status: Option<String>,
+    pub audio_volume: Option<f64>,
+}
+
+impl VehicleExtras {
+    /// Unknown or malformed members end up as None rather than failing the refresh
+    pub fn parse(raw: &Value) -> Self {
+        serde_json::from_value(raw.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SoftwareUpdate {
+    pub status: Option<String>,
+    pub version: Option<String>,
+    pub download_perc: Option<i64>,
+    pub install_perc: Option<i64>,
+    pub expected_duration_sec: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let raw = serde_json::json!({
+            "vin": "5YJ3E7EB0KF000000",
+            "vehicle_state": {
+                "locked": true,
+                "pin_to_drive_enabled": true,
+                "guest_mode_enabled": null,
+                "media_info": {
+                    "now_playing_title": "Bohemian Rhapsody",
+                    "audio_volume": 3.5,
+                },
+                "software_update": {
+                    "status": "available",
+                    "version": "2022.44.25",
+                    "download_perc": 0,
+                },
+                "tpms_pressure_fl": 2.9,
+                "tpms_soft_warning_rr": true,
+                "tpms_hard_warning_fl": false,
+            },
+        });
+        let state = VehicleExtras::parse(&raw).vehicle_state.unwrap();
+        assert_eq!(state.pin_to_drive_enabled, Some(true));
+        assert_eq!(state.guest_mode_enabled, None);
+        let media_info = state.media_info.as_ref().unwrap();
+        assert_eq!(media_info.audio_volume, Some(3.5));
+        assert_eq!(media_info.now_playing_artist, None);
+        let update = state.software_update.as_ref().unwrap();
+        assert_eq!(update.version.as_deref(), Some("2022.44.25"));
+        assert_eq!(update.download_perc, Some(0));
+        assert_eq!(state.tpms_pressure_fl, Some(2.9));
+        assert_eq!(state.tpms_pressure_rr, None);
+        assert!(state.tpms_soft_warning());
+        assert!(!state.tpms_hard_warning());
+
+        let asleep = serde_json::json!({ "vehicle_state": null });
+        assert_eq!(VehicleExtras::parse(&asleep), VehicleExtras::default());
+    }
+}
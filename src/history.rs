@@ -0,0 +1,338 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Local history of the vehicle data in SQLite.
+
+use crate::sessions::{self, ChargingSession};
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use std::path::Path;
+
+/// One refresh of the vehicle data, in the units of the API: miles and °C
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Snapshot {
+    /// unix time in seconds
+    pub timestamp: i64,
+    pub vin: String,
+    pub battery_level: i64,
+    pub battery_range: f64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub inside_temp: Option<f64>,
+    pub outside_temp: Option<f64>,
+    pub charging_state: String,
+    pub charge_energy_added: f64,
+    pub charge_rate: f64,
+    pub charger_type: String,
+    pub odometer: Option<f64>,
+}
+
+impl Snapshot {
+    /// Everything but the time
+    pub fn same_values(&self, other: &Snapshot) -> bool {
+        *self
+            == Snapshot {
+                timestamp: self.timestamp,
+                ..other.clone()
+            }
+    }
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(file: &Path) -> Result<Self, String> {
+        let conn = Connection::open(file)
+            .map_err(|e| format!("Failed to open the history {:?}: {}", file, e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS snapshots (
+                timestamp INTEGER NOT NULL,
+                vin TEXT NOT NULL,
+                battery_level INTEGER NOT NULL,
+                battery_range REAL NOT NULL,
+                latitude REAL,
+                longitude REAL,
+                inside_temp REAL,
+                outside_temp REAL,
+                charging_state TEXT NOT NULL,
+                charge_energy_added REAL NOT NULL,
+                charge_rate REAL NOT NULL,
+                charger_type TEXT NOT NULL,
+                odometer REAL
+            );
+            CREATE INDEX IF NOT EXISTS snapshots_vin_time ON snapshots (vin, timestamp);
+            CREATE TABLE IF NOT EXISTS charging_sessions (
+                vin TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                ended_at INTEGER NOT NULL,
+                latitude REAL,
+                longitude REAL,
+                start_soc INTEGER NOT NULL,
+                end_soc INTEGER NOT NULL,
+                energy_added REAL NOT NULL,
+                peak_charge_rate REAL NOT NULL,
+                charger_type TEXT NOT NULL,
+                PRIMARY KEY (vin, started_at)
+            );",
+        )
+        .map_err(|e| format!("Failed to create the history tables: {}", e))?;
+
+        Ok(History { conn })
+    }
+
+    pub fn insert(&self, s: &Snapshot) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO snapshots (timestamp, vin, battery_level, battery_range, latitude,
+                    longitude, inside_temp, outside_temp, charging_state, charge_energy_added,
+                    charge_rate, charger_type, odometer)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    s.timestamp,
+                    s.vin,
+                    s.battery_level,
+                    s.battery_range,
+                    s.latitude,
+                    s.longitude,
+                    s.inside_temp,
+                    s.outside_temp,
+                    s.charging_state,
+                    s.charge_energy_added,
+                    s.charge_rate,
+                    s.charger_type,
+                    s.odometer,
+                ],
+            )
+            .map_err(|e| format!("Failed to store the snapshot: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Snapshots of the vehicle from `since` onwards, oldest first
+    pub fn query(&self, vin: &str, since: i64) -> Result<Vec<Snapshot>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT timestamp, vin, battery_level, battery_range, latitude, longitude,
+                    inside_temp, outside_temp, charging_state, charge_energy_added,
+                    charge_rate, charger_type, odometer
+                FROM snapshots WHERE vin = ?1 AND timestamp >= ?2 ORDER BY timestamp",
+            )
+            .map_err(|e| format!("Failed to query the history: {}", e))?;
+        let rows = stmt
+            .query_map(params![vin, since], snapshot_from_row)
+            .map_err(|e| format!("Failed to query the history: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read the history: {}", e))
+    }
+
+    /// The last snapshot of every `interval` seconds from `since` onwards, oldest first.
+    /// Keeps long ranges small enough for the UI.
+    pub fn query_sampled(
+        &self,
+        vin: &str,
+        since: i64,
+        interval: i64,
+    ) -> Result<Vec<Snapshot>, String> {
+        // SQLite takes the bare columns from the row with the maximum
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT MAX(timestamp), vin, battery_level, battery_range, latitude, longitude,
+                    inside_temp, outside_temp, charging_state, charge_energy_added,
+                    charge_rate, charger_type, odometer
+                FROM snapshots WHERE vin = ?1 AND timestamp >= ?2
+                GROUP BY (timestamp - ?2) / ?3 ORDER BY 1",
+            )
+            .map_err(|e| format!("Failed to query the history: {}", e))?;
+        let rows = stmt
+            .query_map(params![vin, since, interval.max(1)], snapshot_from_row)
+            .map_err(|e| format!("Failed to query the history: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read the history: {}", e))
+    }
+
+    /// Records the sessions which finished since the last one we know of.
+    /// They are kept when the snapshots are pruned.
+    pub fn update_sessions(&self, vin: &str) -> Result<usize, String> {
+        let first_charging: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT MIN(timestamp) FROM snapshots WHERE vin = ?1 AND charging_state = 'Charging'
+                    AND timestamp > (SELECT COALESCE(MAX(ended_at), -1) FROM charging_sessions
+                        WHERE vin = ?1)",
+                params![vin],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to look for charging sessions: {}", e))?;
+        let since = match first_charging {
+            Some(since) => since,
+            None => return Ok(0),
+        };
+        let found = sessions::detect(&self.query(vin, since)?);
+        for session in &found {
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO charging_sessions (vin, started_at, ended_at, latitude,
+                        longitude, start_soc, end_soc, energy_added, peak_charge_rate, charger_type)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        session.vin,
+                        session.start,
+                        session.end,
+                        session.latitude,
+                        session.longitude,
+                        session.start_soc,
+                        session.end_soc,
+                        session.energy_added,
+                        session.peak_charge_rate,
+                        session.charger_type,
+                    ],
+                )
+                .map_err(|e| format!("Failed to store the charging session: {}", e))?;
+        }
+
+        Ok(found.len())
+    }
+
+    /// Newest first
+    pub fn charging_sessions(&self, vin: &str) -> Result<Vec<ChargingSession>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT vin, started_at, ended_at, latitude, longitude, start_soc, end_soc,
+                    energy_added, peak_charge_rate, charger_type
+                FROM charging_sessions WHERE vin = ?1 ORDER BY started_at DESC",
+            )
+            .map_err(|e| format!("Failed to query the charging sessions: {}", e))?;
+        let rows = stmt
+            .query_map(params![vin], |row| {
+                Ok(ChargingSession {
+                    vin: row.get(0)?,
+                    start: row.get(1)?,
+                    end: row.get(2)?,
+                    latitude: row.get(3)?,
+                    longitude: row.get(4)?,
+                    start_soc: row.get(5)?,
+                    end_soc: row.get(6)?,
+                    energy_added: row.get(7)?,
+                    peak_charge_rate: row.get(8)?,
+                    charger_type: row.get(9)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query the charging sessions: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read the charging sessions: {}", e))
+    }
+
+    /// Retention policy, returns the number of deleted snapshots
+    pub fn prune(&self, before: i64) -> Result<usize, String> {
+        self.conn
+            .execute(
+                "DELETE FROM snapshots WHERE timestamp < ?1",
+                params![before],
+            )
+            .map_err(|e| format!("Failed to prune the history: {}", e))
+    }
+}
+
+fn snapshot_from_row(row: &Row) -> rusqlite::Result<Snapshot> {
+    Ok(Snapshot {
+        timestamp: row.get(0)?,
+        vin: row.get(1)?,
+        battery_level: row.get(2)?,
+        battery_range: row.get(3)?,
+        latitude: row.get(4)?,
+        longitude: row.get(5)?,
+        inside_temp: row.get(6)?,
+        outside_temp: row.get(7)?,
+        charging_state: row.get(8)?,
+        charge_energy_added: row.get(9)?,
+        charge_rate: row.get(10)?,
+        charger_type: row.get(11)?,
+        odometer: row.get(12)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_query_prune() {
+        let history = History::init(Connection::open_in_memory().unwrap()).unwrap();
+        let snapshot = |timestamp, vin: &str| Snapshot {
+            timestamp,
+            vin: vin.to_string(),
+            battery_level: 80,
+            battery_range: 220.5,
+            latitude: Some(47.1),
+            charging_state: "Disconnected".to_string(),
+            ..Default::default()
+        };
+        for (timestamp, vin) in [(100, "A"), (200, "B"), (300, "A"), (400, "A")] {
+            history.insert(&snapshot(timestamp, vin)).unwrap();
+        }
+
+        let found = history.query("A", 200).unwrap();
+        assert_eq!(found, vec![snapshot(300, "A"), snapshot(400, "A")]);
+
+        let sampled = history.query_sampled("A", 0, 250).unwrap();
+        assert_eq!(sampled, vec![snapshot(100, "A"), snapshot(400, "A")]);
+        assert!(snapshot(100, "A").same_values(&snapshot(300, "A")));
+        assert!(!snapshot(100, "A").same_values(&snapshot(100, "B")));
+
+        assert_eq!(history.prune(300).unwrap(), 2);
+        assert_eq!(history.query("A", 0).unwrap().len(), 2);
+        assert!(history.query("B", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sessions_are_recorded_once() {
+        let history = History::init(Connection::open_in_memory().unwrap()).unwrap();
+        let snapshot = |timestamp, state: &str, energy| Snapshot {
+            timestamp,
+            vin: "A".to_string(),
+            charging_state: state.to_string(),
+            charge_energy_added: energy,
+            ..Default::default()
+        };
+        history.insert(&snapshot(10, "Charging", 1.0)).unwrap();
+        history.insert(&snapshot(20, "Charging", 5.0)).unwrap();
+        assert_eq!(history.update_sessions("A").unwrap(), 0);
+
+        history.insert(&snapshot(30, "Disconnected", 0.0)).unwrap();
+        assert_eq!(history.update_sessions("A").unwrap(), 1);
+        assert_eq!(history.update_sessions("A").unwrap(), 0);
+
+        history.insert(&snapshot(40, "Charging", 2.0)).unwrap();
+        history.insert(&snapshot(50, "Complete", 3.0)).unwrap();
+        assert_eq!(history.update_sessions("A").unwrap(), 1);
+
+        // survives the retention policy
+        history.prune(100).unwrap();
+        let sessions = history.charging_sessions("A").unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].start, sessions[0].end), (40, 50));
+        assert_eq!(sessions[1].energy_added, 5.0);
+    }
+}
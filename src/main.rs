@@ -22,18 +22,35 @@ extern crate qmetaobject;
 use qmetaobject::*;
 use qt_core::{q_standard_paths::StandardLocation, QStandardPaths};
 
+mod applock;
+mod cache;
+mod clock;
+mod commands;
 mod constants;
+mod extras;
+mod history;
+mod outbox;
 mod qrc;
+mod retry;
+mod safety;
+mod sessions;
+mod settings;
+mod units;
 
 use serde::Serialize;
 use teslatte::auth::{AccessToken, RefreshToken};
 use teslatte::{
     products::Product,
-    vehicles::{GetVehicleData, SetChargeLimit, SetTemperatures},
+    vehicles::{GetVehicleData, SetChargeLimit, SetTemperatures, VehicleData},
     OwnerApi, VehicleApi, VehicleId,
 };
 
-use std::{env, fs::create_dir_all, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::create_dir_all,
+    path::PathBuf,
+};
 
 use gettextrs::{bindtextdomain, textdomain};
 
@@ -47,26 +64,207 @@ struct ReducedVehicleData {
     pub hvac_enabled: bool,
     pub battery_level: i64,
     pub battery_range: f64,
+    pub range_type: String,
+    pub rated_range: f64,
+    pub ideal_range: f64,
+    pub est_range: f64,
     pub charge_rate: f64,
     pub minutes_to_full_charge: i64,
     pub charge_energy_added: f64,
     pub charge_limit: i64,
+    pub windows: WindowState,
+    pub locked: bool,
+    pub sentry_mode: bool,
+    pub valet_mode: bool,
+    pub speed_limit_active: bool,
+    pub speed_limit: f64,
+    pub speed_limit_pin_set: bool,
+    pub pin_to_drive: Option<bool>,
+    pub guest_mode: Option<bool>,
+    pub homelink_nearby: bool,
+    pub now_playing: Option<NowPlaying>,
+    pub software_update: Option<SoftwareUpdate>,
+    pub tires: Option<TirePressures>,
+    pub units: units::UnitLabels,
+    /// Served from the cache, because the car couldn't be reached
+    pub stale: bool,
+    pub age_sec: u64,
+    pub age: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    pub source: String,
+    pub status: String,
+    pub volume: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct WindowState {
+    pub front_driver: bool,
+    pub front_passenger: bool,
+    pub rear_driver: bool,
+    pub rear_passenger: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct SoftwareUpdate {
+    pub status: String,
+    pub version: String,
+    pub download_percent: i64,
+    pub install_percent: i64,
+    pub expected_duration_sec: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct TirePressures {
+    pub unit: String,
+    pub front_left: Option<f64>,
+    pub front_right: Option<f64>,
+    pub rear_left: Option<f64>,
+    pub rear_right: Option<f64>,
+    pub soft_warning: bool,
+    pub hard_warning: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct VehicleDetails {
+    pub name: String,
+    pub vin: String,
+    pub model: String,
+    pub trim: String,
+    pub odometer: f64,
+    pub car_version: String,
+    pub wheel_type: String,
+    pub paint_color: String,
+    pub charge_port_type: String,
+    pub options: Vec<String>,
+    pub image_url: String,
+}
+
+/// A history snapshot in the units chosen by the user
+#[derive(Debug, Clone, Default, Serialize)]
+struct HistoryPoint {
+    pub timestamp: i64,
+    pub battery_level: i64,
+    pub battery_range: f64,
+    pub gps_pos: String,
+    pub inside_temp: Option<f64>,
+    pub outside_temp: Option<f64>,
+    pub charging_state: String,
+    pub odometer: Option<f64>,
 }
 
+/// A charging session in the units chosen by the user
+#[derive(Debug, Clone, Default, Serialize)]
+struct ChargingSessionItem {
+    pub start: i64,
+    pub end: i64,
+    pub gps_pos: String,
+    pub start_soc: i64,
+    pub end_soc: i64,
+    pub energy_added: f64,
+    pub peak_charge_rate: f64,
+    pub charger_type: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ChargingSite {
+    pub name: String,
+    pub supercharger: bool,
+    pub distance: f64,
+    pub available_stalls: Option<i64>,
+    pub total_stalls: Option<i64>,
+    pub gps_pos: String,
+}
+
+/// How long the car gets to report the effect of a command
+const CONFIRM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// The effect of an accepted command, confirmed with the next vehicle data
+struct Expectation {
+    vin: String,
+    what: &'static str,
+    description: &'static str,
+    check: fn(&VehicleData) -> bool,
+    deadline: std::time::Instant,
+}
+
+/// When checking whether a car needs to be locked again failed
+const RELOCK_RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The retention policy is applied at most that often, unless the setting changes
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
+/// Unchanged values are stored at most that often, e.g. while parked
+const HISTORY_HEARTBEAT_SEC: i64 = 15 * 60;
+
+/// More would only slow down the history page
+const HISTORY_POINTS: i64 = 500;
+
+/// How long a command issued offline waits for the car
+const OUTBOX_TTL: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+/// Takes the index of the vehicle, which might have changed until it is sent
+type QueuedCommand = Box<dyn Fn(&mut Greeter, i64) -> Result<(), retry::Failure>>;
+
 #[derive(QObject, Default)]
 struct Greeter {
     base: qt_base_class!(trait QObject),
     eventlog: std::collections::VecDeque<String>,
     api: Option<OwnerApi>,
-    vehicles: Vec<(VehicleId, String)>,
+    vehicles: Vec<(VehicleId, String, String)>,
+    /// By VIN, the index changes when the vehicles are fetched again
+    vehicle_data: HashMap<String, VehicleData>,
+    sentry_on_lock: qt_property!(bool),
+    distance_unit: qt_property!(QString),
+    temperature_unit: qt_property!(QString),
+    pressure_unit: qt_property!(QString),
+    range_type: qt_property!(QString),
+    preferred_temp: qt_property!(f64),
+    preferred_charge_limit: qt_property!(i64),
+    tpms_front_min: qt_property!(f64),
+    tpms_rear_min: qt_property!(f64),
+    tpms_warned: HashSet<String>,
+    guard: safety::Guard,
+    app_lock: Option<applock::AppLock>,
+    auto_relock_minutes: qt_property!(i32),
+    relock_deadlines: HashMap<String, std::time::Instant>,
+    expectations: Vec<Expectation>,
+    offline: HashSet<String>,
+    outbox: outbox::Outbox<QueuedCommand>,
+    history_days: qt_property!(i32),
+    history: Option<history::History>,
+    /// history_days and when the history was pruned last
+    history_pruned: Option<(i32, std::time::Instant)>,
+    /// The snapshot stored last per VIN
+    history_last: HashMap<String, history::Snapshot>,
 
     login: qt_method!(
         fn login(&mut self) -> QString {
-            self.api = self.log_err(self.log_in());
+            let api = self.ensure_unlocked().and_then(|_| self.log_in());
+            self.api = self.log_err(api);
             let names = self.get_vehicles();
             self.log_err_or(names, "".to_string()).into()
         }
     ),
+    vin: qt_method!(
+        fn vin(&self, idx: i64) -> QString {
+            self.vehicle_vin(idx).unwrap_or_default().into()
+        }
+    ),
+    index_of_vin: qt_method!(
+        fn index_of_vin(&self, vin: QString) -> i64 {
+            // the api doesn't guarantee the order of the vehicles
+            let vin = vin.to_string();
+            self.vehicles
+                .iter()
+                .position(|v| !vin.is_empty() && v.2 == vin)
+                .unwrap_or_default() as i64
+        }
+    ),
     update_log: qt_method!(
         fn update_log(&mut self) -> QString {
             self.eventlog.truncate(5);
@@ -77,6 +275,12 @@ struct Greeter {
                 .into()
         }
     ),
+    check_relock: qt_method!(
+        fn check_relock(&mut self) {
+            let res = self.relock_due();
+            let _ = self.log_err(res);
+        }
+    ),
     get_vehicle_data: qt_method!(
         fn get_vehicle_data(&mut self, idx: i64) -> QString {
             let vehicle = self.get_vehicle(idx);
@@ -84,21 +288,374 @@ struct Greeter {
             self.log_err_or(vehicle, "".to_string()).into()
         }
     ),
+    get_vehicle_details: qt_method!(
+        fn get_vehicle_details(&mut self, idx: i64) -> QString {
+            let details = self.vehicle_details(idx);
+            self.log_err_or(details, "".to_string()).into()
+        }
+    ),
+    get_history: qt_method!(
+        fn get_history(&mut self, idx: i64, hours: i64) -> QString {
+            let points = self.history_points(idx, hours);
+            self.log_err_or(points, "[]".to_string()).into()
+        }
+    ),
+    get_charging_sessions: qt_method!(
+        fn get_charging_sessions(&mut self, idx: i64) -> QString {
+            let sessions = self.charging_sessions(idx);
+            self.log_err_or(sessions, "[]".to_string()).into()
+        }
+    ),
+    get_charging_sites: qt_method!(
+        fn get_charging_sites(&mut self, idx: i64) -> QString {
+            let sites = self.nearby_charging_sites(idx);
+            self.log_err_or(sites, "[]".to_string()).into()
+        }
+    ),
+    // the settings are stored in °C and bar, but shown in the units of the vehicle
+    unit_labels: qt_method!(
+        fn unit_labels(&self, idx: i64) -> QString {
+            serde_json::to_string(&self.units(idx).labels())
+                .unwrap_or_default()
+                .into()
+        }
+    ),
+    user_temperature: qt_method!(
+        fn user_temperature(&self, idx: i64, celsius: f64) -> f64 {
+            self.units(idx).temperature(celsius)
+        }
+    ),
+    to_celsius: qt_method!(
+        fn to_celsius(&self, idx: i64, temperature: f64) -> f64 {
+            self.units(idx).to_celsius(temperature)
+        }
+    ),
+    user_pressure: qt_method!(
+        fn user_pressure(&self, idx: i64, bar: f64) -> f64 {
+            self.units(idx).pressure(bar)
+        }
+    ),
+    to_bar: qt_method!(
+        fn to_bar(&self, idx: i64, pressure: f64) -> f64 {
+            self.units(idx).to_bar(pressure)
+        }
+    ),
     hvac: qt_method!(
         fn hvac(&mut self, idx: i64, enable: bool, temp: i64) {
-            let res = self.enable_hvac(idx, enable, temp);
+            let description = if enable {
+                "turn the HVAC on"
+            } else {
+                "turn the HVAC off"
+            };
+            let res = self.run_or_queue(idx, description, move |g, idx| {
+                g.enable_hvac(idx, enable, temp)
+            });
             let _ = self.log_err(res);
         }
     ),
+    pending_commands: qt_method!(
+        fn pending_commands(&mut self) -> QString {
+            if self.ensure_unlocked().is_err() {
+                return "[]".into();
+            }
+            serde_json::to_string(&self.outbox.pending())
+                .unwrap_or_else(|_| "[]".to_string())
+                .into()
+        }
+    ),
+    cancel_command: qt_method!(
+        fn cancel_command(&mut self, id: i64) {
+            if self.ensure_unlocked().is_err() {
+                return;
+            }
+            if let Some(description) = self.outbox.cancel(id as u64) {
+                self.eventlog
+                    .push_front(format!("cancelled: {}", description));
+            }
+        }
+    ),
+    app_locked: qt_method!(
+        fn app_locked(&mut self) -> bool {
+            self.ensure_unlocked().is_err()
+        }
+    ),
+    app_lock_enabled: qt_method!(
+        fn app_lock_enabled(&mut self) -> bool {
+            self.app_lock().map(|l| l.enabled()).unwrap_or_default()
+        }
+    ),
+    unlock_app: qt_method!(
+        fn unlock_app(&mut self, pin: QString) -> QString {
+            // returns the reason on failure
+            let res = self.app_lock().and_then(|l| l.unlock(&pin.to_string()));
+            res.err().unwrap_or_default().into()
+        }
+    ),
+    lock_app: qt_method!(
+        fn lock_app(&mut self) {
+            if let Ok(lock) = self.app_lock() {
+                if lock.enabled() {
+                    lock.lock();
+                    // the tokens have to be read again after unlocking
+                    self.api = None;
+                }
+            }
+        }
+    ),
+    set_app_pin: qt_method!(
+        fn set_app_pin(&mut self, old_pin: QString, new_pin: QString) -> QString {
+            let res = self
+                .app_lock()
+                .and_then(|l| l.set_pin(&old_pin.to_string(), &new_pin.to_string()));
+            match res {
+                Ok(()) => {
+                    self.eventlog.push_front("app PIN changed".to_string());
+                    QString::default()
+                }
+                Err(e) => e.into(),
+            }
+        }
+    ),
+    read_only: qt_method!(
+        fn read_only(&mut self) -> bool {
+            self.app_lock().map(|l| l.read_only()).unwrap_or_default()
+        }
+    ),
+    set_read_only: qt_method!(
+        fn set_read_only(&mut self, enable: bool, pin: QString) -> QString {
+            // returns the reason on failure
+            let res = self
+                .app_lock()
+                .and_then(|l| l.set_read_only(enable, &pin.to_string()));
+            match res {
+                Ok(()) => {
+                    self.eventlog.push_front(if enable {
+                        "read-only mode enabled".to_string()
+                    } else {
+                        "read-only mode disabled".to_string()
+                    });
+                    QString::default()
+                }
+                Err(e) => e.into(),
+            }
+        }
+    ),
+    confirm_dangerous: qt_method!(
+        fn confirm_dangerous(&mut self) -> bool {
+            // rather ask once too often than not at all
+            self.app_lock()
+                .map(|l| l.confirm_dangerous())
+                .unwrap_or(true)
+        }
+    ),
+    confirm_with_pin: qt_method!(
+        fn confirm_with_pin(&mut self) -> bool {
+            self.app_lock()
+                .map(|l| l.confirm_with_pin())
+                .unwrap_or_default()
+        }
+    ),
+    set_confirmation: qt_method!(
+        fn set_confirmation(
+            &mut self,
+            confirm_dangerous: bool,
+            with_pin: bool,
+            pin: QString,
+        ) -> QString {
+            // returns the reason on failure
+            let res = self
+                .app_lock()
+                .and_then(|l| l.set_confirmation(confirm_dangerous, with_pin, &pin.to_string()));
+            match res {
+                Ok(()) => {
+                    self.eventlog
+                        .push_front("confirmation policy changed".to_string());
+                    QString::default()
+                }
+                Err(e) => e.into(),
+            }
+        }
+    ),
+    request_confirmation: qt_method!(
+        fn request_confirmation(&mut self, action: QString, idx: i64) -> QString {
+            // an empty token means the action can go ahead without asking
+            match safety::Sensitive::parse(&action.to_string()) {
+                Some(_) if self.pin_required() => {
+                    self.eventlog
+                        .push_front("App PIN required for this command".to_string());
+                    QString::default()
+                }
+                Some(action) if self.confirm_dangerous() => self.guard.issue(action, idx).into(),
+                _ => QString::default(),
+            }
+        }
+    ),
+    request_confirmation_pin: qt_method!(
+        fn request_confirmation_pin(&mut self, action: QString, idx: i64, pin: QString) -> QString {
+            let action = match safety::Sensitive::parse(&action.to_string()) {
+                Some(action) => action,
+                None => return QString::default(),
+            };
+            let res = self.app_lock().and_then(|l| l.verify(&pin.to_string()));
+            match self.log_err(res) {
+                Some(()) => self.guard.issue(action, idx).into(),
+                None => QString::default(),
+            }
+        }
+    ),
     doors: qt_method!(
-        fn doors(&mut self, idx: i64, do_open: bool) {
-            let res = self.lock_doors(idx, do_open);
+        fn doors(&mut self, idx: i64, do_open: bool, token: QString) {
+            // unlocking stays interactive, it must not happen once nobody watches
+            let res = if do_open {
+                self.authorize(safety::Sensitive::Unlock, idx, &token.to_string())
+                    .and_then(|_| self.lock_doors(idx, true).map_err(|e| e.to_string()))
+            } else {
+                self.run_or_queue(idx, "lock the doors", move |g, idx| {
+                    g.lock_doors(idx, false)
+                })
+            };
+            let _ = self.log_err(res);
+        }
+    ),
+    trunk: qt_method!(
+        fn trunk(&mut self, idx: i64, front: bool, token: QString) {
+            let action = if front {
+                safety::Sensitive::OpenFrunk
+            } else {
+                safety::Sensitive::OpenTrunk
+            };
+            let res = self
+                .authorize(action, idx, &token.to_string())
+                .and_then(|_| self.actuate_trunk(idx, front));
+            let _ = self.log_err(res);
+        }
+    ),
+    sentry: qt_method!(
+        fn sentry(&mut self, idx: i64, enable: bool, token: QString) {
+            let res = if enable {
+                self.run_or_queue(idx, "enable sentry mode", move |g, idx| {
+                    g.sentry_mode(idx, true)
+                })
+            } else {
+                self.authorize(safety::Sensitive::DisableSentry, idx, &token.to_string())
+                    .and_then(|_| self.sentry_mode(idx, false).map_err(|e| e.to_string()))
+            };
+            let _ = self.log_err(res);
+        }
+    ),
+    valet: qt_method!(
+        fn valet(&mut self, idx: i64, enable: bool, pin: QString) {
+            let res = self.valet_mode(idx, enable, &pin.to_string());
+            let _ = self.log_err(res);
+        }
+    ),
+    speed_limit: qt_method!(
+        fn speed_limit(&mut self, idx: i64, enable: bool, pin: QString) {
+            let res = self.speed_limit_mode(idx, enable, &pin.to_string());
+            let _ = self.log_err(res);
+        }
+    ),
+    set_speed_limit: qt_method!(
+        fn set_speed_limit(&mut self, idx: i64, limit: f64) {
+            let res = self.speed_limit_value(idx, limit);
+            let _ = self.log_err(res);
+        }
+    ),
+    clear_speed_limit_pin: qt_method!(
+        fn clear_speed_limit_pin(&mut self, idx: i64) {
+            let res = self.speed_limit_clear_pin(idx);
+            let _ = self.log_err(res);
+        }
+    ),
+    share_destination: qt_method!(
+        fn share_destination(&mut self, idx: i64, destination: QString) {
+            let res = self.navigate_to(idx, &destination.to_string());
+            let _ = self.log_err(res);
+        }
+    ),
+    trigger_homelink: qt_method!(
+        fn trigger_homelink(&mut self, idx: i64) {
+            let res = self.homelink(idx);
+            let _ = self.log_err(res);
+        }
+    ),
+    media_toggle: qt_method!(
+        fn media_toggle(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_toggle_playback", "playback toggled");
+            let _ = self.log_err(res);
+        }
+    ),
+    media_next_track: qt_method!(
+        fn media_next_track(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_next_track", "next track");
+            let _ = self.log_err(res);
+        }
+    ),
+    media_prev_track: qt_method!(
+        fn media_prev_track(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_prev_track", "previous track");
+            let _ = self.log_err(res);
+        }
+    ),
+    media_next_fav: qt_method!(
+        fn media_next_fav(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_next_fav", "next favorite");
+            let _ = self.log_err(res);
+        }
+    ),
+    media_prev_fav: qt_method!(
+        fn media_prev_fav(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_prev_fav", "previous favorite");
+            let _ = self.log_err(res);
+        }
+    ),
+    volume_up: qt_method!(
+        fn volume_up(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_volume_up", "volume up");
+            let _ = self.log_err(res);
+        }
+    ),
+    volume_down: qt_method!(
+        fn volume_down(&mut self, idx: i64) {
+            let res = self.media_command(idx, "media_volume_down", "volume down");
+            let _ = self.log_err(res);
+        }
+    ),
+    schedule_update: qt_method!(
+        fn schedule_update(&mut self, idx: i64, offset_min: i64) {
+            let res = self.schedule_software_update(idx, offset_min);
+            let _ = self.log_err(res);
+        }
+    ),
+    cancel_update: qt_method!(
+        fn cancel_update(&mut self, idx: i64) {
+            let res = self.cancel_software_update(idx);
+            let _ = self.log_err(res);
+        }
+    ),
+    windows: qt_method!(
+        fn windows(&mut self, idx: i64, do_vent: bool) {
+            let description = if do_vent {
+                "vent the windows"
+            } else {
+                "close the windows"
+            };
+            let res = self.run_or_queue(idx, description, move |g, idx| {
+                g.window_control(idx, do_vent)
+            });
             let _ = self.log_err(res);
         }
     ),
     charge: qt_method!(
         fn charge(&mut self, idx: i64, do_start: bool, charge_limit: u8) {
-            let res = self.charging(idx, do_start, charge_limit);
+            let description = if do_start {
+                "start charging"
+            } else {
+                "stop charging"
+            };
+            let res = self.run_or_queue(idx, description, move |g, idx| {
+                g.charging(idx, do_start, charge_limit)
+            });
             let _ = self.log_err(res);
         }
     ),
@@ -115,267 +672,1271 @@ struct Greeter {
         }
     ),
     drive: qt_method!(
-        fn drive(&mut self, idx: i64) {
-            let res = self.remote_start_drive(idx);
+        fn drive(&mut self, idx: i64, token: QString) {
+            let res = self
+                .authorize(safety::Sensitive::RemoteStart, idx, &token.to_string())
+                .and_then(|_| self.remote_start_drive(idx));
+            let _ = self.log_err(res);
+        }
+    ),
+    pin_drive: qt_method!(
+        fn pin_drive(&mut self, idx: i64, enable: bool, pin: QString) {
+            let res = self.pin_to_drive(idx, enable, &pin.to_string());
+            let _ = self.log_err(res);
+        }
+    ),
+    guest: qt_method!(
+        fn guest(&mut self, idx: i64, enable: bool) {
+            let res = self.guest_mode(idx, enable);
             let _ = self.log_err(res);
         }
     ),
 }
 
 impl Greeter {
-    fn log_in(&self) -> Result<OwnerApi, String> {
-        let app_data_path =
-            unsafe { QStandardPaths::writable_location(StandardLocation::AppDataLocation) };
-        let app_data_path = PathBuf::from(app_data_path.to_std_string());
-        create_dir_all(&app_data_path).map_err(|e| {
-            format!(
-                "Failed to create app data directory {} : {}",
-                app_data_path.display(),
-                e
-            )
-        })?;
-        let access_token_file = app_data_path.join("tesla_access_token.txt");
-        let refresh_token_file = app_data_path.join("tesla_refresh_token.txt");
-
-        let api = if refresh_token_file.exists() {
-            let tok = std::fs::read_to_string(&refresh_token_file)
-                .map_err(|e| {
-                    format!(
-                        "Failed to read the tesla refresh token file {:?}: {}",
-                        refresh_token_file, e
-                    )
-                })?
-                .trim()
-                .to_string();
+    fn log_in(&mut self) -> Result<OwnerApi, String> {
+        // encrypted with a key derived from the app PIN, if one is set
+        let lock = self.app_lock()?;
 
+        let api = if let Some(tok) = lock.read_token(applock::REFRESH_TOKEN)? {
             let rt = tokio::runtime::Runtime::new()
                 .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
 
             let api = rt
                 .block_on(OwnerApi::from_refresh_token(&RefreshToken(tok)))
                 .map_err(|e| format!("failed to refresh token: {:?}", e))?;
-            std::fs::write(&access_token_file, &api.access_token.0)
-                .map_err(|e| format!("failed to write access_token: {:?}", e))?;
+            lock.write_token(applock::ACCESS_TOKEN, &api.access_token.0)
+                .map_err(|e| format!("failed to write access_token: {}", e))?;
             if let Some(refresh_token) = &api.refresh_token {
-                std::fs::write(&refresh_token_file, &refresh_token.0)
-                    .map_err(|e| format!("failed to write refresh_token: {:?}", e))?;
+                lock.write_token(applock::REFRESH_TOKEN, &refresh_token.0)
+                    .map_err(|e| format!("failed to write refresh_token: {}", e))?;
             }
             api
-        } else if access_token_file.exists() {
-            let tok = std::fs::read_to_string(&access_token_file)
-                .map_err(|e| {
-                    format!(
-                        "Failed to read the tesla access token file {:?}: {}",
-                        access_token_file, e
-                    )
-                })?
-                .trim()
-                .to_string();
+        } else if let Some(tok) = lock.read_token(applock::ACCESS_TOKEN)? {
             // println!("token: {}", tok);
             OwnerApi::new(AccessToken(tok), None)
         } else {
-            return Err("not supported yet".to_string());
-            //OwnerApi::from_interactive_url().await.unwrap()
+            return Err("not supported yet".to_string());
+            //OwnerApi::from_interactive_url().await.unwrap()
+        };
+
+        Ok(api)
+    }
+
+    fn get_vehicles(&mut self) -> Result<String, String> {
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+
+        let vehicles = rt
+            .block_on(retry::run(
+                retry::Kind::Read,
+                "products",
+                &mut self.eventlog,
+                || retry::classify(api.products()),
+            ))
+            .map_err(|e| format!("Failed to get vehicles: {}", e))?;
+        self.vehicles = vehicles
+            .iter()
+            .filter_map(|v| match v {
+                Product::Vehicle(veh) => {
+                    let display_name = veh
+                        .display_name
+                        .as_ref()
+                        .unwrap_or(&"".to_string())
+                        .to_string();
+                    let display_name = if display_name.is_empty() {
+                        veh.vin.to_string()
+                    } else {
+                        display_name
+                    };
+                    Some((veh.id.clone(), display_name, veh.vin.to_string()))
+                }
+                _ => None,
+            })
+            .collect();
+        Ok(self
+            .vehicles
+            .iter()
+            .fold("".to_string(), |acc, (_id, name, _vin)| {
+                format!("{}\n{}", acc, name)
+            })
+            .trim()
+            .to_string())
+    }
+
+    /// Falls back to the cached data if the network or the car are down
+    fn get_vehicle(&mut self, idx: i64) -> Result<String, String> {
+        // the cache and the history don't need the api
+        self.ensure_unlocked()?;
+        let vin = self.vehicle_vin(idx)?;
+        let cache = cache::Cache::new(app_data_path()?);
+        match self.fetch_vehicle(idx) {
+            Ok(raw) => {
+                let (vehicle, extras) = parse_vehicle(&raw)?;
+                if let Err(e) = cache.store(&vin, &raw) {
+                    self.eventlog.push_front(e);
+                }
+                let was_offline = self.offline.remove(&vin);
+                let res = self.record_history(snapshot(&vin, &vehicle));
+                if let Some(sessions) = self.log_err(res) {
+                    if sessions > 0 {
+                        self.eventlog
+                            .push_front("charging session recorded".to_string());
+                    }
+                }
+                self.confirm_expectations(&vin, &vehicle);
+                let res = self.reduce_vehicle(idx, vehicle, &extras, None);
+                // only now, so the queued commands see the current data, e.g. the position
+                if was_offline {
+                    self.flush_outbox(&vin, idx);
+                }
+                res
+            }
+            Err(retry::Failure::Fatal(e)) => Err(format!("Failed to get vehicle {}: {}", idx, e)),
+            Err(failure) => {
+                self.offline.insert(vin.clone());
+                let (raw, age_sec) = cache
+                    .load::<serde_json::Value>(&vin)?
+                    .ok_or(format!("Failed to get vehicle {}: {}", idx, failure))?;
+                let (vehicle, extras) = parse_vehicle(&raw)?;
+                self.eventlog.push_front(format!(
+                    "offline, showing data from {} ago: {}",
+                    cache::format_age(age_sec),
+                    failure
+                ));
+                self.reduce_vehicle(idx, vehicle, &extras, Some(age_sec))
+            }
+        }
+    }
+
+    /// Opened on first use
+    fn history(&mut self) -> Result<&history::History, String> {
+        if self.history.is_none() {
+            let history = history::History::open(&app_data_path()?.join("history.sqlite"))?;
+            self.history = Some(history);
+        }
+        self.history
+            .as_ref()
+            .ok_or("History unavailable".to_string())
+    }
+
+    /// Skips snapshots without news, returns the number of charging sessions recorded
+    fn record_history(&mut self, snapshot: history::Snapshot) -> Result<usize, String> {
+        if let Some(last) = self.history_last.get(&snapshot.vin) {
+            if last.same_values(&snapshot)
+                && snapshot.timestamp - last.timestamp < HISTORY_HEARTBEAT_SEC
+            {
+                return Ok(0);
+            }
+        }
+        self.history()?.insert(&snapshot)?;
+        let vin = snapshot.vin.clone();
+        self.history_last.insert(vin.clone(), snapshot);
+        self.prune_history()?;
+        self.history()?.update_sessions(&vin)
+    }
+
+    /// Retention policy, applied once a day and right away when the setting changed
+    fn prune_history(&mut self) -> Result<(), String> {
+        let days = self.history_days;
+        let due = match self.history_pruned {
+            Some((pruned_days, at)) => pruned_days != days || at.elapsed() >= PRUNE_INTERVAL,
+            None => true,
+        };
+        if days <= 0 || !due {
+            return Ok(());
+        }
+        let before = clock::now_sec() as i64 - i64::from(days) * 24 * 3600;
+        let pruned = self.history()?.prune(before)?;
+        self.history_pruned = Some((days, std::time::Instant::now()));
+        if pruned > 0 {
+            self.eventlog
+                .push_front(format!("removed {} old history entries", pruned));
+        }
+
+        Ok(())
+    }
+
+    fn history_points(&mut self, idx: i64, hours: i64) -> Result<String, String> {
+        self.ensure_unlocked()?;
+        let vin = self.vehicle_vin(idx)?;
+        let units = self.units(idx);
+        let since = clock::now_sec() as i64 - hours * 3600;
+        let points: Vec<HistoryPoint> = self
+            .history()?
+            .query_sampled(&vin, since, hours * 3600 / HISTORY_POINTS)?
+            .into_iter()
+            .map(|s| HistoryPoint {
+                timestamp: s.timestamp,
+                battery_level: s.battery_level,
+                battery_range: units.distance(s.battery_range),
+                gps_pos: match (s.latitude, s.longitude) {
+                    (Some(lat), Some(lon)) => format!("{},{}", lat, lon),
+                    _ => "".to_string(),
+                },
+                inside_temp: s.inside_temp.map(|t| units.temperature(t)),
+                outside_temp: s.outside_temp.map(|t| units.temperature(t)),
+                charging_state: s.charging_state,
+                odometer: s.odometer.map(|o| units.distance(o)),
+            })
+            .collect();
+        serde_json::to_string(&points)
+            .map_err(|e| format!("Failed to serialize the history: {:?}", e))
+    }
+
+    fn charging_sessions(&mut self, idx: i64) -> Result<String, String> {
+        self.ensure_unlocked()?;
+        let vin = self.vehicle_vin(idx)?;
+        let units = self.units(idx);
+        let items: Vec<ChargingSessionItem> = self
+            .history()?
+            .charging_sessions(&vin)?
+            .into_iter()
+            .map(|s| ChargingSessionItem {
+                start: s.start,
+                end: s.end,
+                gps_pos: match (s.latitude, s.longitude) {
+                    (Some(lat), Some(lon)) => format!("{},{}", lat, lon),
+                    _ => "".to_string(),
+                },
+                start_soc: s.start_soc,
+                end_soc: s.end_soc,
+                energy_added: s.energy_added,
+                peak_charge_rate: units.distance(s.peak_charge_rate),
+                charger_type: s.charger_type,
+            })
+            .collect();
+        serde_json::to_string(&items)
+            .map_err(|e| format!("Failed to serialize the charging sessions: {:?}", e))
+    }
+
+    /// Runs the command right away, or queues it while the vehicle is offline
+    /// or when it didn't get to the car
+    fn run_or_queue<F>(&mut self, idx: i64, description: &str, command: F) -> Result<(), String>
+    where
+        F: Fn(&mut Greeter, i64) -> Result<(), retry::Failure> + 'static,
+    {
+        let vin = self.vehicle_vin(idx)?;
+        if !self.offline.contains(&vin) {
+            match command(self, idx) {
+                // the car can't have received it
+                Err(e) if e.retryable(retry::Kind::Command) => {
+                    self.eventlog.push_front(e.to_string());
+                    // the next successful refresh sends it
+                    self.offline.insert(vin.clone());
+                }
+                res => return res.map_err(|e| e.to_string()),
+            }
+        }
+        self.writable()?;
+        self.outbox
+            .push(&vin, description, OUTBOX_TTL, Box::new(command));
+        self.eventlog.push_front(format!(
+            "queued until the car is reachable: {}",
+            description
+        ));
+
+        Ok(())
+    }
+
+    /// Sends what was queued while the vehicle was offline, in the original order
+    fn flush_outbox(&mut self, vin: &str, idx: i64) {
+        let (commands, expired) = self.outbox.take(vin);
+        for description in expired {
+            self.eventlog
+                .push_front(format!("expired while offline: {}", description));
+        }
+        for (description, expires, command) in commands {
+            // once the car is gone again, the rest waits as well to keep the order
+            if self.offline.contains(vin) {
+                self.outbox.push_until(vin, &description, expires, command);
+                continue;
+            }
+            match command(self, idx) {
+                Ok(()) => self
+                    .eventlog
+                    .push_front(format!("sent queued command: {}", description)),
+                Err(e) if e.retryable(retry::Kind::Command) => {
+                    self.eventlog
+                        .push_front(format!("queued again: {}: {}", description, e));
+                    self.offline.insert(vin.to_string());
+                    self.outbox.push_until(vin, &description, expires, command);
+                }
+                Err(e) => self.eventlog.push_front(format!(
+                    "Failed to send the queued command to {}: {}",
+                    description, e
+                )),
+            }
+        }
+    }
+
+    /// The raw response, teslatte drops the members it doesn't model
+    fn fetch_vehicle(&mut self, idx: i64) -> Result<serde_json::Value, retry::Failure> {
+        let api = self
+            .api
+            .as_ref()
+            .ok_or(retry::Failure::Fatal("Not logged in".to_string()))?;
+        let rt = tokio::runtime::Runtime::new().map_err(|e| {
+            retry::Failure::Fatal(format!("Failed to create a tokio runtime: {}", e))
+        })?;
+
+        let vid = &self.vehicles[idx as usize].0;
+        // no point in waiting for the retries while the car is known to be offline
+        let kind = if self.offline.contains(&self.vehicles[idx as usize].2) {
+            retry::Kind::Probe
+        } else {
+            retry::Kind::Read
+        };
+        let _ = rt.block_on(retry::run(kind, "wake_up", &mut self.eventlog, || {
+            retry::classify(api.wake_up(vid))
+        }))?;
+        rt.block_on(retry::run(kind, "vehicle_data", &mut self.eventlog, || {
+            commands::get(api, vid, "vehicle_data")
+        }))
+    }
+
+    fn reduce_vehicle(
+        &mut self,
+        idx: i64,
+        vehicle: VehicleData,
+        extras: &extras::VehicleExtras,
+        age_sec: Option<u64>,
+    ) -> Result<String, String> {
+        let units = self.units_for(&vehicle);
+        let state = vehicle.state.to_string();
+        let gps_pos = if let Some(drive_state) = &vehicle.drive_state {
+            format!(
+                "{},{}",
+                drive_state.latitude.unwrap_or_default(),
+                drive_state.longitude.unwrap_or_default()
+            )
+        } else {
+            "".to_string()
+        };
+        let inside_temp = if let Some(climate_state) = &vehicle.climate_state {
+            if let Some(itemp) = climate_state.inside_temp {
+                format!("{:.1}", units.temperature(f64::from(itemp)))
+            } else {
+                "".to_string()
+            }
+        } else {
+            "".to_string()
+        };
+        let outside_temp = if let Some(climate_state) = &vehicle.climate_state {
+            if let Some(otemp) = climate_state.outside_temp {
+                format!("{:.1}", units.temperature(f64::from(otemp)))
+            } else {
+                "".to_string()
+            }
+        } else {
+            "".to_string()
+        };
+        let driver_temp_setting = if let Some(climate_state) = &vehicle.climate_state {
+            units
+                .temperature(climate_state.driver_temp_setting as f64)
+                .round() as i64
+        } else {
+            units.temperature(self.preferred_temp).round() as i64
+        };
+        let hvac_enabled = if let Some(climate_state) = &vehicle.climate_state {
+            climate_state.fan_status != 0
+        } else {
+            false
+        };
+        let range_type = units::RangeType::resolve(
+            &self.range_type.to_string(),
+            vehicle
+                .gui_settings
+                .as_ref()
+                .map(|g| g.gui_range_display.as_str())
+                .unwrap_or_default(),
+        );
+        let (
+            battery_level,
+            (rated_range, ideal_range, est_range),
+            charge_rate,
+            minutes_to_full_charge,
+            charge_energy_added,
+            charge_limit,
+        ) = if let Some(charge_state) = &vehicle.charge_state {
+            (
+                charge_state.battery_level,
+                (
+                    units.distance(charge_state.battery_range),
+                    units.distance(charge_state.ideal_battery_range),
+                    units.distance(charge_state.est_battery_range),
+                ),
+                units.distance(charge_state.charge_rate),
+                charge_state.minutes_to_full_charge,
+                charge_state.charge_energy_added,
+                charge_state.charge_limit_soc,
+            )
+        } else {
+            (0, (0.0, 0.0, 0.0), 0.0, 0, 0.0, self.preferred_charge_limit)
+        };
+        let battery_range = range_type.select(rated_range, ideal_range, est_range);
+        let windows = if let Some(vehicle_state) = &vehicle.vehicle_state {
+            WindowState {
+                front_driver: vehicle_state.fd_window != 0,
+                front_passenger: vehicle_state.fp_window != 0,
+                rear_driver: vehicle_state.rd_window != 0,
+                rear_passenger: vehicle_state.rp_window != 0,
+            }
+        } else {
+            WindowState::default()
+        };
+        let (locked, sentry_mode) = if let Some(vehicle_state) = &vehicle.vehicle_state {
+            (
+                vehicle_state.locked,
+                vehicle_state.sentry_mode.unwrap_or_default(),
+            )
+        } else {
+            (false, false)
+        };
+        let (valet_mode, speed_limit_active, speed_limit, speed_limit_pin_set) =
+            if let Some(vehicle_state) = &vehicle.vehicle_state {
+                let speed_limit_mode = &vehicle_state.speed_limit_mode;
+                (
+                    vehicle_state.valet_mode,
+                    speed_limit_mode.active,
+                    units.distance(speed_limit_mode.current_limit_mph),
+                    speed_limit_mode.pin_code_set,
+                )
+            } else {
+                (false, false, 0.0, false)
+            };
+        let (pin_to_drive, guest_mode) = if let Some(extra_state) = &extras.vehicle_state {
+            (
+                extra_state.pin_to_drive_enabled,
+                extra_state.guest_mode_enabled,
+            )
+        } else {
+            (None, None)
+        };
+        let homelink_nearby = if let Some(vehicle_state) = &vehicle.vehicle_state {
+            vehicle_state.homelink_nearby.unwrap_or_default()
+        } else {
+            false
+        };
+        let now_playing = extras
+            .vehicle_state
+            .as_ref()
+            .and_then(|extra_state| extra_state.media_info.as_ref())
+            .and_then(|media_info| {
+                let text = |value: &Option<String>| value.clone().unwrap_or_default();
+                let title = text(&media_info.now_playing_title);
+                if title.is_empty() {
+                    return None;
+                }
+                Some(NowPlaying {
+                    title,
+                    artist: text(&media_info.now_playing_artist),
+                    source: text(&media_info.now_playing_source),
+                    status: text(&media_info.media_playback_status),
+                    volume: media_info.audio_volume.unwrap_or_default(),
+                })
+            });
+        let software_update = extras
+            .vehicle_state
+            .as_ref()
+            .and_then(|extra_state| extra_state.software_update.as_ref())
+            .and_then(|update| {
+                // an empty status means there is no update pending
+                let status = update.status.clone().unwrap_or_default();
+                if status.is_empty() {
+                    return None;
+                }
+                Some(SoftwareUpdate {
+                    status,
+                    version: update.version.clone().unwrap_or_default(),
+                    download_percent: update.download_perc.unwrap_or_default(),
+                    install_percent: update.install_perc.unwrap_or_default(),
+                    expected_duration_sec: update.expected_duration_sec.unwrap_or_default(),
+                })
+            });
+        let tires = extras.vehicle_state.as_ref().map(|extra_state| {
+            let pressure = |bar: Option<f64>| bar.map(|bar| units.pressure(bar));
+            TirePressures {
+                unit: units.pressure.name().to_string(),
+                front_left: pressure(extra_state.tpms_pressure_fl),
+                front_right: pressure(extra_state.tpms_pressure_fr),
+                rear_left: pressure(extra_state.tpms_pressure_rl),
+                rear_right: pressure(extra_state.tpms_pressure_rr),
+                soft_warning: extra_state.tpms_soft_warning(),
+                hard_warning: extra_state.tpms_hard_warning(),
+            }
+        });
+        if let Some(tires) = &tires {
+            self.check_tire_pressures(idx, tires, &units);
+        }
+        let vehicle_data = ReducedVehicleData {
+            state,
+            gps_pos,
+            inside_temp,
+            outside_temp,
+            driver_temp_setting,
+            hvac_enabled,
+            battery_level,
+            battery_range,
+            range_type: range_type.name().to_string(),
+            rated_range,
+            ideal_range,
+            est_range,
+            charge_rate,
+            minutes_to_full_charge,
+            charge_energy_added,
+            charge_limit,
+            windows,
+            locked,
+            sentry_mode,
+            valet_mode,
+            speed_limit_active,
+            speed_limit,
+            speed_limit_pin_set,
+            pin_to_drive,
+            guest_mode,
+            homelink_nearby,
+            now_playing,
+            software_update,
+            tires,
+            units: units.labels(),
+            stale: age_sec.is_some(),
+            age_sec: age_sec.unwrap_or_default(),
+            age: age_sec.map(cache::format_age).unwrap_or_default(),
+        };
+        if let Ok(vin) = self.vehicle_vin(idx) {
+            self.vehicle_data.insert(vin, vehicle);
+        }
+        serde_json::to_string(&vehicle_data)
+            .map_err(|e| format!("Failed to serialize ReducedVehicleData: {:?}", e))
+    }
+
+    /// Warn once in the event log when a tire drops below the configured threshold
+    /// The thresholds are configured in bar
+    fn check_tire_pressures(&mut self, idx: i64, tires: &TirePressures, units: &units::Units) {
+        let front_min = units.pressure(self.tpms_front_min);
+        let rear_min = units.pressure(self.tpms_rear_min);
+        let below = |pressure: Option<f64>, min: f64| {
+            min > 0.0 && pressure.map(|p| p < min).unwrap_or_default()
+        };
+        let low = [
+            ("front left", below(tires.front_left, front_min)),
+            ("front right", below(tires.front_right, front_min)),
+            ("rear left", below(tires.rear_left, rear_min)),
+            ("rear right", below(tires.rear_right, rear_min)),
+        ]
+        .iter()
+        .filter(|(_, low)| *low)
+        .map(|(wheel, _)| *wheel)
+        .collect::<Vec<_>>();
+        let vin = self.vehicle_vin(idx).unwrap_or_default();
+        if low.is_empty() && !tires.hard_warning {
+            self.tpms_warned.remove(&vin);
+        } else if self.tpms_warned.insert(vin) {
+            self.eventlog.push_front(format!(
+                "Low tire pressure {}: {}",
+                self.vehicles[idx as usize].1,
+                if low.is_empty() {
+                    "reported by the car".to_string()
+                } else {
+                    low.join(", ")
+                }
+            ));
+        }
+    }
+
+    /// Details about the car from the data fetched last by get_vehicle()
+    fn vehicle_details(&mut self, idx: i64) -> Result<String, String> {
+        self.ensure_unlocked()?;
+        let vehicle = self
+            .vehicle(idx)
+            .ok_or(format!("No data for vehicle {} yet", idx))?;
+        let config = vehicle.vehicle_config.as_ref();
+        let state = vehicle.vehicle_state.as_ref();
+
+        let units = self.units(idx);
+        let options: Vec<String> = vehicle
+            .option_codes
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|o| o.trim().to_string())
+            .filter(|o| !o.is_empty())
+            .collect();
+        let model = config.map(|c| c.car_type.clone()).unwrap_or_default();
+        let details = VehicleDetails {
+            name: self.vehicles[idx as usize].1.clone(),
+            vin: vehicle.vin.clone(),
+            trim: config
+                .and_then(|c| c.trim_badging.clone())
+                .unwrap_or_default(),
+            odometer: units.distance(state.map(|s| s.odometer).unwrap_or_default()),
+            car_version: state.map(|s| s.car_version.clone()).unwrap_or_default(),
+            wheel_type: config.map(|c| c.wheel_type.clone()).unwrap_or_default(),
+            paint_color: config.map(|c| c.exterior_color.clone()).unwrap_or_default(),
+            charge_port_type: config
+                .map(|c| c.charge_port_type.clone())
+                .unwrap_or_default(),
+            image_url: car_image_url(&model, &options),
+            model,
+            options,
+        };
+        serde_json::to_string(&details)
+            .map_err(|e| format!("Failed to serialize VehicleDetails: {:?}", e))
+    }
+
+    /// Superchargers and destination chargers around the car, the closest first
+    fn nearby_charging_sites(&mut self, idx: i64) -> Result<String, String> {
+        self.ensure_unlocked()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let resp = rt
+            .block_on(retry::run(
+                retry::Kind::Read,
+                "nearby_charging_sites",
+                &mut self.eventlog,
+                || commands::get(api, vid, "nearby_charging_sites"),
+            ))
+            .map_err(|e| format!("Failed to get nearby charging sites {}: {}", idx, e))?;
+        let units = self.units(idx);
+        let site = |s: &serde_json::Value, supercharger: bool| ChargingSite {
+            name: s["name"].as_str().unwrap_or_default().to_string(),
+            supercharger,
+            distance: units.distance(s["distance_miles"].as_f64().unwrap_or_default()),
+            available_stalls: s["available_stalls"].as_i64(),
+            total_stalls: s["total_stalls"].as_i64(),
+            gps_pos: format!(
+                "{},{}",
+                s["location"]["lat"].as_f64().unwrap_or_default(),
+                s["location"]["long"].as_f64().unwrap_or_default()
+            ),
+        };
+        let list = |name: &str| resp[name].as_array().cloned().unwrap_or_default();
+        let mut sites: Vec<ChargingSite> = list("superchargers")
+            .iter()
+            .filter(|s| !s["site_closed"].as_bool().unwrap_or_default())
+            .map(|s| site(s, true))
+            .chain(list("destination_charging").iter().map(|s| site(s, false)))
+            .collect();
+        sites.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+        serde_json::to_string(&sites)
+            .map_err(|e| format!("Failed to serialize the charging sites: {:?}", e))
+    }
+
+    fn enable_hvac(&mut self, idx: i64, enable: bool, temp: i64) -> Result<(), retry::Failure> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let units = self.units(idx);
+        let celsius = units.to_celsius(temp as f64) as f32;
+        let temps = SetTemperatures {
+            driver_temp: celsius,
+            passenger_temp: celsius,
+        };
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "set_temps",
+            &mut self.eventlog,
+            || retry::classify(api.set_temps(vid, &temps)),
+        ))
+        .map_err(|e| e.context(&format!("Failed to set hvac temperature {}", idx)))
+        .and_then(|r| commands::check_result("set_temps", &r).map_err(retry::Failure::Fatal))?;
+        let response = if enable {
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "auto_conditioning_start",
+                &mut self.eventlog,
+                || retry::classify(api.auto_conditioning_start(vid)),
+            ))
+        } else {
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "auto_conditioning_stop",
+                &mut self.eventlog,
+                || retry::classify(api.auto_conditioning_stop(vid)),
+            ))
+        }
+        .map_err(|e| e.context(&format!("Failed to enable or disable hvac {}", idx)))?;
+        commands::check_result("auto_conditioning", &response)?;
+        if enable {
+            self.expect(idx, "hvac", "HVAC on", |v| climate_on(v) == Some(true));
+        } else {
+            self.expect(idx, "hvac", "HVAC off", |v| climate_on(v) == Some(false));
+        }
+        if enable {
+            self.eventlog.push_front(format!(
+                "HVAC enabled to {}{}",
+                temp,
+                units.labels().temperature
+            ));
+        } else {
+            self.eventlog.push_front("HVAC disabled".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn lock_doors(&mut self, idx: i64, do_open: bool) -> Result<(), retry::Failure> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let response = if do_open {
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "door_unlock",
+                &mut self.eventlog,
+                || retry::classify(api.door_unlock(vid)),
+            ))
+        } else {
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "door_lock",
+                &mut self.eventlog,
+                || retry::classify(api.door_lock(vid)),
+            ))
+        }
+        .map_err(|e| e.context(&format!("Failed to (un)-lock the doors {}", idx)))?;
+        commands::check_result("door_lock", &response)?;
+        if do_open {
+            self.expect(idx, "doors", "doors unlocked", |v| {
+                v.vehicle_state.as_ref().is_some_and(|s| !s.locked)
+            });
+        } else {
+            self.expect(idx, "doors", "doors locked", |v| {
+                v.vehicle_state.as_ref().is_some_and(|s| s.locked)
+            });
+        }
+        if do_open {
+            self.eventlog.push_front("doors unlocked".to_string());
+            if self.auto_relock_minutes > 0 {
+                let delay = std::time::Duration::from_secs(self.auto_relock_minutes as u64 * 60);
+                let vin = self.vehicle_vin(idx)?;
+                self.relock_deadlines
+                    .insert(vin, std::time::Instant::now() + delay);
+            }
+        } else {
+            self.eventlog.push_front("doors locked".to_string());
+            let vin = self.vehicle_vin(idx)?;
+            self.relock_deadlines.remove(&vin);
+            // the doors are locked already, so this mustn't queue the lock again
+            if self.sentry_on_lock {
+                if let Err(e) = self.sentry_mode(idx, true) {
+                    self.eventlog.push_front(e.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces an older expectation about the same thing, e.g. lock after unlock
+    fn expect(
+        &mut self,
+        idx: i64,
+        what: &'static str,
+        description: &'static str,
+        check: fn(&VehicleData) -> bool,
+    ) {
+        let vin = match self.vehicle_vin(idx) {
+            Ok(vin) => vin,
+            Err(_) => return,
+        };
+        self.expectations.retain(|e| e.vin != vin || e.what != what);
+        self.expectations.push(Expectation {
+            vin,
+            what,
+            description,
+            check,
+            deadline: std::time::Instant::now() + CONFIRM_TIMEOUT,
+        });
+    }
+
+    /// Logs whether the car actually did what it accepted
+    fn confirm_expectations(&mut self, vin: &str, vehicle: &VehicleData) {
+        let now = std::time::Instant::now();
+        let eventlog = &mut self.eventlog;
+        self.expectations.retain(|e| {
+            if e.vin != vin {
+                true
+            } else if (e.check)(vehicle) {
+                eventlog.push_front(format!("confirmed by the car: {}", e.description));
+                false
+            } else if e.deadline <= now {
+                eventlog.push_front(format!("not confirmed by the car: {}", e.description));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Locks the vehicles again which were unlocked from the app, but nobody got in.
+    /// The deadline stays until the car could be checked.
+    fn relock_due(&mut self) -> Result<(), String> {
+        // locked app, they are due again once it is unlocked and logged in
+        if self.api.is_none() {
+            return Ok(());
+        }
+        let now = std::time::Instant::now();
+        let due: Vec<String> = self
+            .relock_deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(vin, _)| vin.clone())
+            .collect();
+        for vin in due {
+            let idx = match self.vehicles.iter().position(|v| v.2 == vin) {
+                Some(idx) => idx as i64,
+                None => continue,
+            };
+            match self.relock(idx) {
+                Ok(()) => {
+                    self.relock_deadlines.remove(&vin);
+                }
+                Err(e) => {
+                    self.relock_deadlines.insert(vin, now + RELOCK_RETRY);
+                    self.eventlog.push_front(format!(
+                        "Failed to relock {}, trying again in {} s: {}",
+                        self.vehicles[idx as usize].1,
+                        RELOCK_RETRY.as_secs(),
+                        e
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn relock(&mut self, idx: i64) -> Result<(), String> {
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let request = GetVehicleData::new(vid.clone());
+        let vehicle = rt
+            .block_on(retry::run(
+                retry::Kind::Read,
+                "vehicle_data",
+                &mut self.eventlog,
+                || retry::classify(api.vehicle_data(&request)),
+            ))
+            .map_err(|e| format!("Failed to get vehicle {}: {}", idx, e))?;
+        let vehicle_state = vehicle
+            .vehicle_state
+            .as_ref()
+            .ok_or(format!("No vehicle state for vehicle {}", idx))?;
+        if !vehicle_state.locked && !vehicle_state.is_user_present {
+            self.lock_doors(idx, false).map_err(|e| e.to_string())?;
+            self.eventlog.push_front(format!(
+                "{} was still unlocked, locked it again",
+                self.vehicles[idx as usize].1
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn actuate_trunk(&mut self, idx: i64, front: bool) -> Result<(), String> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "which_trunk": if front { "front" } else { "rear" } });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "actuate_trunk",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "actuate_trunk", &body),
+        ))
+        .map_err(|e| format!("Failed to open the trunk {}: {}", idx, e))?;
+        if front {
+            self.eventlog.push_front("frunk opened".to_string());
+        } else {
+            self.eventlog.push_front("trunk actuated".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn sentry_mode(&mut self, idx: i64, enable: bool) -> Result<(), retry::Failure> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "on": enable });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "set_sentry_mode",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "set_sentry_mode", &body),
+        ))
+        .map_err(|e| e.context(&format!("Failed to enable or disable sentry mode {}", idx)))?;
+        if enable {
+            self.expect(idx, "sentry", "sentry mode on", |v| {
+                v.vehicle_state.as_ref().and_then(|s| s.sentry_mode) == Some(true)
+            });
+        } else {
+            self.expect(idx, "sentry", "sentry mode off", |v| {
+                v.vehicle_state.as_ref().and_then(|s| s.sentry_mode) == Some(false)
+            });
+        }
+        if enable {
+            self.eventlog.push_front("sentry mode enabled".to_string());
+        } else {
+            self.eventlog.push_front("sentry mode disabled".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn valet_mode(&mut self, idx: i64, enable: bool, pin: &str) -> Result<(), String> {
+        self.writable()?;
+        check_pin(pin)?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "on": enable, "password": pin });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "set_valet_mode",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "set_valet_mode", &body),
+        ))
+        .map_err(|e| format!("Failed to enable or disable valet mode {}: {}", idx, e))?;
+        if enable {
+            self.eventlog.push_front("valet mode enabled".to_string());
+        } else {
+            self.eventlog.push_front("valet mode disabled".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn speed_limit_mode(&mut self, idx: i64, enable: bool, pin: &str) -> Result<(), String> {
+        self.writable()?;
+        check_pin(pin)?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let command = if enable {
+            "speed_limit_activate"
+        } else {
+            "speed_limit_deactivate"
         };
+        let body = serde_json::json!({ "pin": pin });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            command,
+            &mut self.eventlog,
+            || commands::post_command(api, vid, command, &body),
+        ))
+        .map_err(|e| format!("Failed to enable or disable speed limit {}: {}", idx, e))?;
+        if enable {
+            self.eventlog
+                .push_front("speed limit mode enabled".to_string());
+        } else {
+            self.eventlog
+                .push_front("speed limit mode disabled".to_string());
+        }
 
-        Ok(api)
+        Ok(())
     }
 
-    fn get_vehicles(&mut self) -> Result<String, String> {
+    fn speed_limit_value(&mut self, idx: i64, limit: f64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
 
-        let vehicles = rt
-            .block_on(api.products())
-            .map_err(|e| format!("Failed to get vehicles: {}", e))?;
-        self.vehicles = vehicles
-            .iter()
-            .filter_map(|v| match v {
-                Product::Vehicle(veh) => {
-                    let display_name = veh
-                        .display_name
-                        .as_ref()
-                        .unwrap_or(&"".to_string())
-                        .to_string();
-                    let display_name = if display_name.is_empty() {
-                        veh.vin.to_string()
-                    } else {
-                        display_name
-                    };
-                    Some((veh.id.clone(), display_name))
-                }
-                _ => None,
-            })
-            .collect();
-        Ok(self
-            .vehicles
-            .iter()
-            .fold("".to_string(), |acc, (_id, name)| {
-                format!("{}\n{}", acc, name)
-            })
-            .trim()
-            .to_string())
+        let units = self.units(idx);
+        // the car only accepts 50 to 90 mph, and 80 km/h is just below
+        let mph = units.to_miles(limit).clamp(50.0, 90.0);
+        let body = serde_json::json!({ "limit_mph": mph });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "speed_limit_set_limit",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "speed_limit_set_limit", &body),
+        ))
+        .map_err(|e| format!("Failed to set the speed limit {}: {}", idx, e))?;
+        self.eventlog.push_front(format!(
+            "speed limit set to {:.0}{}",
+            limit,
+            units.labels().speed
+        ));
+
+        Ok(())
     }
 
-    fn get_vehicle(&mut self, idx: i64) -> Result<String, String> {
+    fn speed_limit_clear_pin(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        // the admin variant doesn't need the forgotten pin
+        let body = serde_json::json!({});
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "speed_limit_clear_pin_admin",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "speed_limit_clear_pin_admin", &body),
+        ))
+        .map_err(|e| format!("Failed to clear the speed limit pin {}: {}", idx, e))?;
+        self.eventlog
+            .push_front("speed limit pin cleared".to_string());
 
+        Ok(())
+    }
+
+    fn navigate_to(&mut self, idx: i64, destination: &str) -> Result<(), String> {
+        self.writable()?;
+        let destination = destination.trim();
+        if destination.is_empty() {
+            return Err("No destination given".to_string());
+        }
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
-        let _ = rt
-            .block_on(api.wake_up(vid))
-            .map_err(|e| format!("Failed to wake up vehicle {}: {}", idx, e))?;
-        let vehicle = rt
-            .block_on(api.vehicle_data(&GetVehicleData::new(vid.clone())))
-            .map_err(|e| format!("Failed to get vehicle {}: {}", idx, e))?;
 
-        let state = vehicle.state.to_string();
-        let gps_pos = if let Some(drive_state) = &vehicle.drive_state {
-            format!(
-                "{},{}",
-                drive_state.latitude.unwrap_or_default(),
-                drive_state.longitude.unwrap_or_default()
-            )
-        } else {
-            "".to_string()
-        };
-        let inside_temp = if let Some(climate_state) = &vehicle.climate_state {
-            if let Some(itemp) = climate_state.inside_temp {
-                format!("{}", itemp)
-            } else {
-                "".to_string()
-            }
-        } else {
-            "".to_string()
-        };
-        let outside_temp = if let Some(climate_state) = &vehicle.climate_state {
-            if let Some(otemp) = climate_state.outside_temp {
-                format!("{}", otemp)
-            } else {
-                "".to_string()
-            }
-        } else {
-            "".to_string()
-        };
-        let driver_temp_setting = if let Some(climate_state) = &vehicle.climate_state {
-            climate_state.driver_temp_setting as i64
-        } else {
-            20
-        };
-        let hvac_enabled = if let Some(climate_state) = &vehicle.climate_state {
-            climate_state.fan_status != 0
-        } else {
-            false
-        };
-        let (
-            battery_level,
-            battery_range,
-            charge_rate,
-            minutes_to_full_charge,
-            charge_energy_added,
-            charge_limit,
-        ) = if let Some(charge_state) = &vehicle.charge_state {
-            const MILE_TO_KM: f64 = 1.609344;
-            (
-                charge_state.battery_level,
-                charge_state.ideal_battery_range * MILE_TO_KM,
-                charge_state.charge_rate,
-                charge_state.minutes_to_full_charge,
-                charge_state.charge_energy_added,
-                charge_state.charge_limit_soc,
-            )
+        let res = if let Some((lat, lon)) = parse_coordinates(destination) {
+            let body = serde_json::json!({ "lat": lat, "lon": lon, "order": 0 });
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "navigation_gps_request",
+                &mut self.eventlog,
+                || commands::post_command(api, vid, "navigation_gps_request", &body),
+            ))
         } else {
-            (0, 0.0, 0.0, 0, 0.0, 80)
-        };
-        let vehicle_data = ReducedVehicleData {
-            state,
-            gps_pos,
-            inside_temp,
-            outside_temp,
-            driver_temp_setting,
-            hvac_enabled,
-            battery_level,
-            battery_range,
-            charge_rate,
-            minutes_to_full_charge,
-            charge_energy_added,
-            charge_limit,
+            let timestamp_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or_default();
+            let body = serde_json::json!({
+                "type": "share_ext_content_raw",
+                "value": { "android.intent.extra.TEXT": destination },
+                "locale": "en-US",
+                "timestamp_ms": timestamp_ms.to_string(),
+            });
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "share",
+                &mut self.eventlog,
+                || commands::post_command(api, vid, "share", &body),
+            ))
         };
-        serde_json::to_string(&vehicle_data)
-            .map_err(|e| format!("Failed to serialize ReducedVehicleData: {:?}", e))
+        res.map_err(|e| format!("Failed to share the destination {}: {}", idx, e))?;
+        self.eventlog
+            .push_front(format!("destination sent: {}", destination));
+
+        Ok(())
     }
 
-    fn enable_hvac(&mut self, idx: i64, enable: bool, temp: i64) -> Result<(), String> {
+    fn homelink(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let temps = SetTemperatures {
-            driver_temp: temp as f32,
-            passenger_temp: temp as f32,
-        };
-        let _ = rt
-            .block_on(api.set_temps(vid, &temps))
-            .map_err(|e| format!("Failed to set hvac temperature {}: {}", idx, e))?;
-        let _ = if enable {
-            rt.block_on(api.auto_conditioning_start(vid))
-        } else {
-            rt.block_on(api.auto_conditioning_stop(vid))
-        }
-        .map_err(|e| format!("Failed to enable or disable hvac {}: {}", idx, e))?;
-        if enable {
-            self.eventlog
-                .push_front(format!("HVAC enabled to {}°C", temp));
-        } else {
-            self.eventlog.push_front("HVAC disabled".to_string());
+        let nearby = self
+            .vehicle(idx)
+            .and_then(|v| v.vehicle_state.as_ref())
+            .and_then(|s| s.homelink_nearby)
+            .unwrap_or_default();
+        if !nearby {
+            return Err(format!("No HomeLink device near vehicle {}", idx));
         }
+        let (lat, lon) = self
+            .position(idx)
+            .ok_or(format!("Position of vehicle {} unknown", idx))?;
+        let body = serde_json::json!({ "lat": lat, "lon": lon });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "trigger_homelink",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "trigger_homelink", &body),
+        ))
+        .map_err(|e| format!("Failed to trigger HomeLink {}: {}", idx, e))?;
+        self.eventlog.push_front("HomeLink triggered".to_string());
 
         Ok(())
     }
 
-    fn lock_doors(&mut self, idx: i64, do_open: bool) -> Result<(), String> {
+    fn media_command(&mut self, idx: i64, command: &str, msg: &str) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let _ = if do_open {
-            rt.block_on(api.door_unlock(vid))
-        } else {
-            rt.block_on(api.door_lock(vid))
-        }
-        .map_err(|e| format!("Failed to (un)-lock the doors {}: {}", idx, e))?;
-        if do_open {
-            self.eventlog.push_front("doors unlocked".to_string());
+        let body = serde_json::json!({});
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            command,
+            &mut self.eventlog,
+            || commands::post_command(api, vid, command, &body),
+        ))
+        .map_err(|e| format!("Failed to control the media player {}: {}", idx, e))?;
+        self.eventlog.push_front(msg.to_string());
+
+        Ok(())
+    }
+
+    fn schedule_software_update(&mut self, idx: i64, offset_min: i64) -> Result<(), String> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "offset_sec": offset_min.max(0) * 60 });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "schedule_software_update",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "schedule_software_update", &body),
+        ))
+        .map_err(|e| format!("Failed to schedule the software update {}: {}", idx, e))?;
+        self.eventlog.push_front(format!(
+            "software update scheduled in {} minutes",
+            offset_min.max(0)
+        ));
+
+        Ok(())
+    }
+
+    fn cancel_software_update(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({});
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "cancel_software_update",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "cancel_software_update", &body),
+        ))
+        .map_err(|e| format!("Failed to cancel the software update {}: {}", idx, e))?;
+        self.eventlog
+            .push_front("software update cancelled".to_string());
+
+        Ok(())
+    }
+
+    fn window_control(&mut self, idx: i64, do_vent: bool) -> Result<(), retry::Failure> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        // closing the windows is only accepted close to the car
+        let (lat, lon) = self
+            .position(idx)
+            .ok_or(format!("Position of vehicle {} unknown", idx))?;
+        let body = serde_json::json!({
+            "command": if do_vent { "vent" } else { "close" },
+            "lat": lat,
+            "lon": lon,
+        });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "window_control",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "window_control", &body),
+        ))
+        .map_err(|e| e.context(&format!("Failed to vent/close the windows {}", idx)))?;
+        if do_vent {
+            self.eventlog.push_front("windows vented".to_string());
         } else {
-            self.eventlog.push_front("doors locked".to_string());
+            self.eventlog.push_front("windows closed".to_string());
         }
 
         Ok(())
     }
 
-    fn charging(&mut self, idx: i64, do_start: bool, charge_limit: u8) -> Result<(), String> {
+    fn charging(
+        &mut self,
+        idx: i64,
+        do_start: bool,
+        charge_limit: u8,
+    ) -> Result<(), retry::Failure> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let _ = if do_start {
+        let response = if do_start {
             let limit = SetChargeLimit {
                 percent: charge_limit,
             };
-            if let Err(e) = rt.block_on(api.set_charge_limit(vid, &limit)) {
+            if let Err(e) = rt
+                .block_on(retry::run(
+                    retry::Kind::Command,
+                    "set_charge_limit",
+                    &mut self.eventlog,
+                    || retry::classify(api.set_charge_limit(vid, &limit)),
+                ))
+                .map_err(|e| e.to_string())
+                .and_then(|r| commands::check_result("set_charge_limit", &r))
+            {
                 self.eventlog
                     .push_front(format!("Failed to set charge limit {}: {}", idx, e));
             }
-            rt.block_on(api.charge_start(vid))
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "charge_start",
+                &mut self.eventlog,
+                || retry::classify(api.charge_start(vid)),
+            ))
+        } else {
+            rt.block_on(retry::run(
+                retry::Kind::Command,
+                "charge_stop",
+                &mut self.eventlog,
+                || retry::classify(api.charge_stop(vid)),
+            ))
+        }
+        .map_err(|e| e.context(&format!("Failed to start/stop charging {}", idx)))?;
+        commands::check_result("charging", &response)?;
+        if do_start {
+            self.expect(idx, "charging", "charging", |v| {
+                charging_state(v).as_deref() == Some("Charging")
+            });
         } else {
-            rt.block_on(api.charge_stop(vid))
+            self.expect(idx, "charging", "charging stopped", |v| {
+                charging_state(v).is_some_and(|s| s != "Charging")
+            });
         }
-        .map_err(|e| format!("Failed to start/stop charging {}: {}", idx, e))?;
         if do_start {
             self.eventlog
                 .push_front(format!("charging started up to {}%", charge_limit));
@@ -387,48 +1948,213 @@ impl Greeter {
     }
 
     fn honk_horn(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let _ = rt
-            .block_on(api.honk_horn(vid))
-            .map_err(|e| format!("Failed to honk the horn {}: {}", idx, e))?;
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "honk_horn",
+            &mut self.eventlog,
+            || retry::classify(api.honk_horn(vid)),
+        ))
+        .map_err(|e| format!("Failed to honk the horn {}: {}", idx, e))
+        .and_then(|r| commands::check_result("honk_horn", &r))?;
         self.eventlog.push_front("horn honked".to_string());
 
         Ok(())
     }
 
     fn flash_lights(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let _ = rt
-            .block_on(api.flash_lights(vid))
-            .map_err(|e| format!("Failed to flash the lights {}: {}", idx, e))?;
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "flash_lights",
+            &mut self.eventlog,
+            || retry::classify(api.flash_lights(vid)),
+        ))
+        .map_err(|e| format!("Failed to flash the lights {}: {}", idx, e))
+        .and_then(|r| commands::check_result("flash_lights", &r))?;
         self.eventlog.push_front("lights flashed".to_string());
 
         Ok(())
     }
 
     fn remote_start_drive(&mut self, idx: i64) -> Result<(), String> {
+        self.writable()?;
         let api = self.api.as_ref().ok_or("Not logged in")?;
         let rt = tokio::runtime::Runtime::new()
             .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
         let vid = &self.vehicles[idx as usize].0;
 
-        let _ = rt
-            .block_on(api.remote_start_drive(vid))
-            .map_err(|e| format!("Failed allow keyless driving {}: {}", idx, e))?;
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "remote_start_drive",
+            &mut self.eventlog,
+            || retry::classify(api.remote_start_drive(vid)),
+        ))
+        .map_err(|e| format!("Failed allow keyless driving {}: {}", idx, e))
+        .and_then(|r| commands::check_result("remote_start_drive", &r))?;
         self.eventlog
             .push_front("Keyless driving active for two minutes".to_string());
 
         Ok(())
     }
 
+    fn pin_to_drive(&mut self, idx: i64, enable: bool, pin: &str) -> Result<(), String> {
+        self.writable()?;
+        check_pin(pin)?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "on": enable, "password": pin });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "set_pin_to_drive",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "set_pin_to_drive", &body),
+        ))
+        .map_err(|e| format!("Failed to enable or disable pin to drive {}: {}", idx, e))?;
+        if enable {
+            self.eventlog.push_front("PIN to drive enabled".to_string());
+        } else {
+            self.eventlog
+                .push_front("PIN to drive disabled".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn guest_mode(&mut self, idx: i64, enable: bool) -> Result<(), String> {
+        self.writable()?;
+        let api = self.api.as_ref().ok_or("Not logged in")?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let vid = &self.vehicles[idx as usize].0;
+
+        let body = serde_json::json!({ "enable": enable });
+        rt.block_on(retry::run(
+            retry::Kind::Command,
+            "guest_mode",
+            &mut self.eventlog,
+            || commands::post_command(api, vid, "guest_mode", &body),
+        ))
+        .map_err(|e| format!("Failed to enable or disable guest mode {}: {}", idx, e))?;
+        if enable {
+            self.eventlog.push_front("guest mode enabled".to_string());
+        } else {
+            self.eventlog.push_front("guest mode disabled".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn vehicle_vin(&self, idx: i64) -> Result<String, String> {
+        self.vehicles
+            .get(idx as usize)
+            .map(|v| v.2.clone())
+            .ok_or(format!("Unknown vehicle {}", idx))
+    }
+
+    fn app_lock(&mut self) -> Result<&mut applock::AppLock, String> {
+        if self.app_lock.is_none() {
+            let lock = applock::AppLock::load(app_data_path()?.join("app_lock.json"))?;
+            self.app_lock = Some(lock);
+        }
+        self.app_lock
+            .as_mut()
+            .ok_or("App lock unavailable".to_string())
+    }
+
+    fn ensure_unlocked(&mut self) -> Result<(), String> {
+        if self.app_lock()?.is_unlocked() {
+            Ok(())
+        } else {
+            Err("The app is locked".to_string())
+        }
+    }
+
+    /// Viewers may look at the vehicles, but not send any commands
+    fn writable(&mut self) -> Result<(), String> {
+        self.ensure_unlocked()?;
+        if self.app_lock()?.read_only() {
+            Err("Read-only mode: commands are disabled on this device".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// With an app PIN set, confirmations can be bound to re-entering it
+    fn pin_required(&mut self) -> bool {
+        self.confirm_dangerous()
+            && self.confirm_with_pin()
+            && self.app_lock().map(|l| l.enabled()).unwrap_or_default()
+    }
+
+    /// Sensitive commands need a token from request_confirmation() unless disabled
+    fn authorize(
+        &mut self,
+        action: safety::Sensitive,
+        idx: i64,
+        token: &str,
+    ) -> Result<(), String> {
+        if !self.confirm_dangerous() {
+            return Ok(());
+        }
+        self.guard.redeem(action, idx, token)
+    }
+
+    /// Units for the vehicle as fetched last, falling back to the defaults
+    fn units(&self, idx: i64) -> units::Units {
+        match self.vehicle(idx) {
+            Some(vehicle) => self.units_for(vehicle),
+            None => units::Units::resolve(
+                &self.distance_unit.to_string(),
+                &self.temperature_unit.to_string(),
+                &self.pressure_unit.to_string(),
+                "",
+                "",
+            ),
+        }
+    }
+
+    /// The user's unit settings, where "auto" follows the car's gui settings
+    fn units_for(&self, vehicle: &VehicleData) -> units::Units {
+        let gui_settings = vehicle.gui_settings.as_ref();
+        units::Units::resolve(
+            &self.distance_unit.to_string(),
+            &self.temperature_unit.to_string(),
+            &self.pressure_unit.to_string(),
+            gui_settings
+                .map(|g| g.gui_distance_units.as_str())
+                .unwrap_or_default(),
+            gui_settings
+                .map(|g| g.gui_temperature_units.as_str())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// As fetched last by get_vehicle()
+    fn vehicle(&self, idx: i64) -> Option<&VehicleData> {
+        let vin = &self.vehicles.get(idx as usize)?.2;
+        self.vehicle_data.get(vin)
+    }
+
+    /// Last known position of the vehicle as fetched by get_vehicle()
+    fn position(&self, idx: i64) -> Option<(f64, f64)> {
+        let drive_state = self.vehicle(idx)?.drive_state.as_ref()?;
+        Some((drive_state.latitude?, drive_state.longitude?))
+    }
+
     fn log_err<T>(&mut self, res: Result<T, String>) -> Option<T> {
         match res {
             Ok(d) => Some(d),
@@ -453,6 +2179,112 @@ impl Greeter {
     }
 }
 
+/// Writable directory for tokens, settings and caches
+pub fn app_data_path() -> Result<PathBuf, String> {
+    let app_data_path =
+        unsafe { QStandardPaths::writable_location(StandardLocation::AppDataLocation) };
+    let app_data_path = PathBuf::from(app_data_path.to_std_string());
+    create_dir_all(&app_data_path).map_err(|e| {
+        format!(
+            "Failed to create app data directory {} : {}",
+            app_data_path.display(),
+            e
+        )
+    })?;
+
+    Ok(app_data_path)
+}
+
+/// Rendering of the car from the tesla configurator matching model and options
+fn car_image_url(car_type: &str, options: &[String]) -> String {
+    let model = match car_type.to_lowercase().as_str() {
+        "models" | "models2" => "ms",
+        "modelx" => "mx",
+        "modely" => "my",
+        _ => "m3",
+    };
+    format!(
+        "https://static-assets.tesla.com/configurator/compositor?model={}&view=STUD_3QTR&size=800&bkba_opt=1&options={}",
+        model,
+        options.join(",")
+    )
+}
+
+/// Accepts "lat,lon" as well as geo: URIs like the ones the Map button opens
+fn parse_coordinates(destination: &str) -> Option<(f64, f64)> {
+    let coords = destination.strip_prefix("geo:").unwrap_or(destination);
+    let coords = coords.split(['?', ';']).next()?;
+    let (lat, lon) = coords.split_once(',')?;
+    let lat = lat.trim().parse::<f64>().ok()?;
+    let lon = lon.trim().parse::<f64>().ok()?;
+    if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+        Some((lat, lon))
+    } else {
+        None
+    }
+}
+
+/// Both views of the raw vehicle_data response
+fn parse_vehicle(raw: &serde_json::Value) -> Result<(VehicleData, extras::VehicleExtras), String> {
+    let vehicle = serde_json::from_value(raw.clone())
+        .map_err(|e| format!("Failed to parse the vehicle data: {}", e))?;
+
+    Ok((vehicle, extras::VehicleExtras::parse(raw)))
+}
+
+fn climate_on(vehicle: &VehicleData) -> Option<bool> {
+    Some(vehicle.climate_state.as_ref()?.is_climate_on)
+}
+
+/// What goes into the history, in the units of the API
+fn snapshot(vin: &str, vehicle: &VehicleData) -> history::Snapshot {
+    let mut snapshot = history::Snapshot {
+        timestamp: clock::now_sec() as i64,
+        vin: vin.to_string(),
+        charging_state: charging_state(vehicle).unwrap_or_default(),
+        ..Default::default()
+    };
+    if let Some(charge_state) = &vehicle.charge_state {
+        snapshot.battery_level = charge_state.battery_level;
+        snapshot.battery_range = charge_state.battery_range;
+        snapshot.charge_energy_added = charge_state.charge_energy_added;
+        snapshot.charge_rate = charge_state.charge_rate;
+        // e.g. "Tesla" or "CCS" at fast chargers, the cable ("IEC", "SAE") otherwise
+        snapshot.charger_type = if charge_state.fast_charger_present {
+            charge_state.fast_charger_type.clone()
+        } else {
+            charge_state.conn_charge_cable.clone()
+        };
+    }
+    if let Some(drive_state) = &vehicle.drive_state {
+        snapshot.latitude = drive_state.latitude;
+        snapshot.longitude = drive_state.longitude;
+    }
+    if let Some(climate_state) = &vehicle.climate_state {
+        snapshot.inside_temp = climate_state.inside_temp.map(f64::from);
+        snapshot.outside_temp = climate_state.outside_temp.map(f64::from);
+    }
+    if let Some(vehicle_state) = &vehicle.vehicle_state {
+        snapshot.odometer = Some(vehicle_state.odometer);
+    }
+
+    snapshot
+}
+
+/// "Charging", "Complete", "Stopped", "Disconnected", ...
+fn charging_state(vehicle: &VehicleData) -> Option<String> {
+    Some(vehicle.charge_state.as_ref()?.charging_state.clone())
+}
+
+/// Valet, speed limit mode and PIN to drive are protected by a four digit pin
+fn check_pin(pin: &str) -> Result<(), String> {
+    if pin.len() == 4 && pin.chars().all(|c| c.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err("The pin has to consist of four digits".to_string())
+    }
+}
+
 #[cfg(not(test))]
 fn main() {
     init_gettext();
@@ -468,6 +2300,7 @@ fn main() {
     QQuickStyle::set_style("Suru");
     qrc::load();
     qml_register_type::<Greeter>(cstr!("Greeter"), 1, 0, cstr!("Greeter"));
+    qml_register_type::<settings::AppSettings>(cstr!("Greeter"), 1, 0, cstr!("AppSettings"));
     let mut engine = QmlEngine::new();
 
     println!("Loading file /qml/uttesla.qml.");
@@ -510,20 +2343,31 @@ mod tests {
         println!("access_token: {}", tok);
         let api = OwnerApi::new(AccessToken(tok), None);
 
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
         let vehicles = rt.block_on(api.vehicles()).unwrap();
         println!("{:?}", vehicles);
         dbg!(&vehicles);
     }
 
+    #[test]
+    fn test_parse_coordinates() {
+        assert_eq!(parse_coordinates("47.3769,8.5417"), Some((47.3769, 8.5417)));
+        assert_eq!(
+            parse_coordinates("geo:47.3769,8.5417?z=15"),
+            Some((47.3769, 8.5417))
+        );
+        assert_eq!(parse_coordinates("Bahnhofstrasse 1, Zurich"), None);
+        assert_eq!(parse_coordinates("91,8"), None);
+    }
+
     #[test]
     #[should_panic(expected = "DecodeJsonError")]
     fn test_login_fail() {
         let tok = "ThisIsNotAValidAccessToken".to_string();
         println!("access_token: {}", tok);
-        let api = OwnerOwnerApi::new(AccessToken(tok), None);
+        let api = OwnerApi::new(AccessToken(tok), None);
 
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
         let vehicles = rt.block_on(api.vehicles()).unwrap();
         println!("{:?}", vehicles);
         dbg!(&vehicles);
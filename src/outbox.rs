@@ -0,0 +1,152 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Commands issued while the car was out of reach, sent in order once it is back.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// What the UI shows of a queued command
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingCommand {
+    pub id: u64,
+    pub vin: String,
+    pub description: String,
+    pub expires_in_sec: u64,
+}
+
+struct Entry<C> {
+    id: u64,
+    vin: String,
+    description: String,
+    expires: Instant,
+    command: C,
+}
+
+pub struct Outbox<C> {
+    entries: VecDeque<Entry<C>>,
+    next_id: u64,
+}
+
+impl<C> Default for Outbox<C> {
+    fn default() -> Self {
+        Outbox {
+            entries: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl<C> Outbox<C> {
+    pub fn push(&mut self, vin: &str, description: &str, ttl: Duration, command: C) -> u64 {
+        self.push_until(vin, description, Instant::now() + ttl, command)
+    }
+
+    /// Queues it again with what is left of its lifetime
+    pub fn push_until(
+        &mut self,
+        vin: &str,
+        description: &str,
+        expires: Instant,
+        command: C,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push_back(Entry {
+            id,
+            vin: vin.to_string(),
+            description: description.to_string(),
+            expires,
+            command,
+        });
+
+        id
+    }
+
+    pub fn cancel(&mut self, id: u64) -> Option<String> {
+        let pos = self.entries.iter().position(|e| e.id == id)?;
+        self.entries.remove(pos).map(|e| e.description)
+    }
+
+    pub fn pending(&self) -> Vec<PendingCommand> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter(|e| e.expires > now)
+            .map(|e| PendingCommand {
+                id: e.id,
+                vin: e.vin.clone(),
+                description: e.description.clone(),
+                expires_in_sec: (e.expires - now).as_secs(),
+            })
+            .collect()
+    }
+
+    /// Removes the commands for the vehicle, returns the ones still valid in the order
+    /// they were issued with their expiry, and the descriptions of the expired ones
+    pub fn take(&mut self, vin: &str) -> (Vec<(String, Instant, C)>, Vec<String>) {
+        let now = Instant::now();
+        let mut valid = Vec::new();
+        let mut expired = Vec::new();
+        let mut others = VecDeque::new();
+        for entry in self.entries.drain(..) {
+            if entry.vin != vin {
+                others.push_back(entry);
+            } else if entry.expires > now {
+                valid.push((entry.description, entry.expires, entry.command));
+            } else {
+                expired.push(entry.description);
+            }
+        }
+        self.entries = others;
+
+        (valid, expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_expiry_and_cancel() {
+        let mut outbox = Outbox::default();
+        let ttl = Duration::from_secs(60);
+        outbox.push("A", "HVAC on", ttl, 'a');
+        let lock = outbox.push("A", "lock the doors", ttl, 'b');
+        outbox.push("B", "start charging", ttl, 'c');
+        outbox.push("A", "close the windows", Duration::ZERO, 'd');
+        outbox.push("A", "sentry mode on", ttl, 'e');
+        assert_eq!(outbox.pending().len(), 4);
+
+        assert_eq!(outbox.cancel(lock), Some("lock the doors".to_string()));
+        assert_eq!(outbox.cancel(lock), None);
+
+        let (valid, expired) = outbox.take("A");
+        let commands: Vec<char> = valid.iter().map(|(_, _, c)| *c).collect();
+        assert_eq!(commands, vec!['a', 'e']);
+        assert_eq!(expired, vec!["close the windows".to_string()]);
+        assert_eq!(outbox.pending()[0].description, "start charging");
+
+        // queued again, it keeps its original expiry
+        let (description, expires, command) = valid.into_iter().next().unwrap();
+        outbox.push_until("A", &description, expires, command);
+        let (valid, _) = outbox.take("A");
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].1, expires);
+    }
+}
@@ -2,6 +2,12 @@ qrc!(qml_resources,
     "/" {
         "qml/uttesla.qml",
         "qml/MainPage.qml",
+        "qml/VehicleDetails.qml",
+        "qml/ChargingSites.qml",
+        "qml/SettingsPage.qml",
+        "qml/LockPage.qml",
+        "qml/HistoryPage.qml",
+        "qml/ChargingSessions.qml",
     },
 );
 
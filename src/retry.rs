@@ -0,0 +1,265 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Exponential backoff with jitter for flaky mobile connections and sleepy cars.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+const ATTEMPTS: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+/// Everything blocks the UI meanwhile, so we rather give up than wait longer
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Idempotent, repeating it can't do any harm
+    Read,
+    /// Only repeated if the car can't have received it
+    Command,
+    /// Tried once, e.g. whether a car known to be offline is back
+    Probe,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// Never reached the server, e.g. no network
+    NotSent(String),
+    /// HTTP 429, with the Retry-After delay if the server sent one
+    RateLimited(Option<Duration>, String),
+    /// Refused before it got to the car: 408 vehicle unavailable, 502, 503
+    Unavailable(String),
+    /// Sent, but we don't know whether the car got it: timeouts, 500, 504
+    Unknown(String),
+    /// No point in trying again
+    Fatal(String),
+}
+
+impl Failure {
+    pub fn from_status(status: u16, retry_after: Option<&str>, msg: String) -> Self {
+        match status {
+            429 => Self::RateLimited(
+                retry_after
+                    .and_then(|s| s.trim().parse().ok())
+                    .map(Duration::from_secs),
+                msg,
+            ),
+            408 | 502 | 503 => Self::Unavailable(msg),
+            500..=599 => Self::Unknown(msg),
+            _ => Self::Fatal(msg),
+        }
+    }
+
+    /// Classifies errors from teslatte or reqwest by the reqwest::Error in the source chain
+    pub fn from_error<E: Error + 'static>(e: &E) -> Self {
+        let msg = e.to_string();
+        let mut source: Option<&(dyn Error + 'static)> = Some(e);
+        while let Some(err) = source {
+            if let Some(req) = err.downcast_ref::<reqwest::Error>() {
+                return if req.is_connect() {
+                    Self::NotSent(msg)
+                } else if let Some(status) = req.status() {
+                    Self::from_status(status.as_u16(), None, msg)
+                } else if req.is_builder() || req.is_decode() {
+                    Self::Fatal(msg)
+                } else {
+                    Self::Unknown(msg)
+                };
+            }
+            source = err.source();
+        }
+        // teslatte reports server errors only as text, which also contains the url with the
+        // vehicle id, so we only look for the phrases of the server and not for status codes
+        let lower = msg.to_lowercase();
+        if lower.contains("too many requests") || lower.contains("rate limit") {
+            Self::RateLimited(None, msg)
+        } else if lower.contains("vehicle unavailable") || lower.contains("vehicle is offline") {
+            Self::Unavailable(msg)
+        } else {
+            Self::Fatal(msg)
+        }
+    }
+
+    /// Prefixes the message, keeping the classification
+    pub fn context(self, what: &str) -> Self {
+        let with = |msg: String| format!("{}: {}", what, msg);
+        match self {
+            Self::NotSent(msg) => Self::NotSent(with(msg)),
+            Self::RateLimited(after, msg) => Self::RateLimited(after, with(msg)),
+            Self::Unavailable(msg) => Self::Unavailable(with(msg)),
+            Self::Unknown(msg) => Self::Unknown(with(msg)),
+            Self::Fatal(msg) => Self::Fatal(with(msg)),
+        }
+    }
+
+    pub fn retryable(&self, kind: Kind) -> bool {
+        match self {
+            Self::NotSent(_) | Self::RateLimited(_, _) | Self::Unavailable(_) => {
+                kind != Kind::Probe
+            }
+            Self::Unknown(_) => kind == Kind::Read,
+            Self::Fatal(_) => false,
+        }
+    }
+
+    /// None if the server wants us to wait longer than we are willing to
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Option<Duration> {
+        if let Self::RateLimited(Some(after), _) = self {
+            return if *after <= MAX_DELAY {
+                Some(*after)
+            } else {
+                None
+            };
+        }
+        let exp = BASE_DELAY
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_DELAY);
+        // between half and the full delay, so several clients don't retry in lockstep
+        Some(exp.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RateLimited(Some(after), msg) => {
+                write!(f, "{} (retry after {} seconds)", msg, after.as_secs())
+            }
+            Self::NotSent(msg)
+            | Self::RateLimited(None, msg)
+            | Self::Unavailable(msg)
+            | Self::Unknown(msg)
+            | Self::Fatal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Errors that never made it to the network, like a missing login
+impl From<String> for Failure {
+    fn from(msg: String) -> Self {
+        Self::Fatal(msg)
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(msg: &str) -> Self {
+        Self::Fatal(msg.to_string())
+    }
+}
+
+/// Turns the error of a teslatte call into a Failure
+pub async fn classify<T, E: Error + 'static>(
+    fut: impl Future<Output = Result<T, E>>,
+) -> Result<T, Failure> {
+    fut.await.map_err(|e| Failure::from_error(&e))
+}
+
+/// Runs the request again as long as it is safe and worth it, reporting each retry
+pub async fn run<T, F, Fut>(
+    kind: Kind,
+    what: &str,
+    eventlog: &mut VecDeque<String>,
+    mut request: F,
+) -> Result<T, Failure>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Failure>>,
+{
+    let mut attempt = 0;
+    loop {
+        let failure = match request().await {
+            Ok(res) => return Ok(res),
+            Err(failure) => failure,
+        };
+        attempt += 1;
+        if attempt >= ATTEMPTS || !failure.retryable(kind) {
+            return Err(failure);
+        }
+        let delay = match failure.delay(attempt - 1, jitter()) {
+            Some(delay) => delay,
+            None => return Err(failure),
+        };
+        eventlog.push_front(format!(
+            "retrying {} in {:.1}s: {}",
+            what,
+            delay.as_secs_f64(),
+            failure
+        ));
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 0.0 .. 1.0
+fn jitter() -> f64 {
+    (OsRng.next_u32() % 1000) as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_safe_commands_are_retried() {
+        let rate_limited = Failure::from_status(429, Some("5"), "slow down".to_string());
+        assert_eq!(
+            rate_limited,
+            Failure::RateLimited(Some(Duration::from_secs(5)), "slow down".to_string())
+        );
+        assert!(rate_limited.retryable(Kind::Command));
+        assert!(Failure::from_status(408, None, "".to_string()).retryable(Kind::Command));
+
+        let timeout = Failure::from_status(504, None, "".to_string());
+        assert!(timeout.retryable(Kind::Read));
+        assert!(!timeout.retryable(Kind::Command));
+        assert!(!Failure::from_status(401, None, "".to_string()).retryable(Kind::Read));
+        assert!(!Failure::NotSent("".to_string()).retryable(Kind::Probe));
+
+        // ids in the url must not be mistaken for a status
+        let text = std::io::Error::other(
+            "GET /api/1/vehicles/1492931408/vehicle_data: vehicle unavailable",
+        );
+        assert!(matches!(
+            Failure::from_error(&text),
+            Failure::Unavailable(_)
+        ));
+        let text = std::io::Error::other(
+            "GET /api/1/vehicles/74290429/vehicle_data: invalid bearer token",
+        );
+        assert!(matches!(Failure::from_error(&text), Failure::Fatal(_)));
+
+        let offline = Failure::NotSent("no network".to_string()).context("Failed to lock");
+        assert_eq!(
+            offline,
+            Failure::NotSent("Failed to lock: no network".to_string())
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let failure = Failure::Unavailable("asleep".to_string());
+        assert_eq!(failure.delay(0, 1.0), Some(Duration::from_secs(1)));
+        assert_eq!(failure.delay(2, 0.0), Some(Duration::from_secs(2)));
+        assert_eq!(failure.delay(10, 1.0), Some(MAX_DELAY));
+
+        let after = |secs| Failure::RateLimited(Some(Duration::from_secs(secs)), "".to_string());
+        assert_eq!(after(3).delay(0, 0.0), Some(Duration::from_secs(3)));
+        assert_eq!(after(600).delay(0, 0.0), None);
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! One-time confirmation tokens for commands that shouldn't fire on a pocket tap.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use std::time::{Duration, Instant};
+
+const TOKEN_VALIDITY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sensitive {
+    Unlock,
+    RemoteStart,
+    OpenTrunk,
+    OpenFrunk,
+    DisableSentry,
+}
+
+impl Sensitive {
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "unlock" => Some(Self::Unlock),
+            "remote_start" => Some(Self::RemoteStart),
+            "open_trunk" => Some(Self::OpenTrunk),
+            "open_frunk" => Some(Self::OpenFrunk),
+            "disable_sentry" => Some(Self::DisableSentry),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Unlock => "unlock the doors",
+            Self::RemoteStart => "allow keyless driving",
+            Self::OpenTrunk => "open the trunk",
+            Self::OpenFrunk => "open the frunk",
+            Self::DisableSentry => "disable sentry mode",
+        }
+    }
+}
+
+struct Pending {
+    token: String,
+    action: Sensitive,
+    idx: i64,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct Guard {
+    pending: Vec<Pending>,
+}
+
+impl Guard {
+    /// Hands out a token which authorizes the action on the vehicle once
+    pub fn issue(&mut self, action: Sensitive, idx: i64) -> String {
+        let now = Instant::now();
+        self.pending.retain(|p| p.expires > now);
+        let token = format!("{:016x}", OsRng.next_u64());
+        self.pending.push(Pending {
+            token: token.clone(),
+            action,
+            idx,
+            expires: now + TOKEN_VALIDITY,
+        });
+
+        token
+    }
+
+    pub fn redeem(&mut self, action: Sensitive, idx: i64, token: &str) -> Result<(), String> {
+        let now = Instant::now();
+        self.pending.retain(|p| p.expires > now);
+        let pos = self
+            .pending
+            .iter()
+            .position(|p| {
+                p.action == action && p.idx == idx && !token.is_empty() && p.token == token
+            })
+            .ok_or(format!("Confirmation required to {}", action.description()))?;
+        self.pending.remove(pos);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_single_use() {
+        let mut guard = Guard::default();
+        let token = guard.issue(Sensitive::Unlock, 0);
+        assert!(guard.redeem(Sensitive::Unlock, 1, &token).is_err());
+        assert!(guard.redeem(Sensitive::RemoteStart, 0, &token).is_err());
+        assert!(guard.redeem(Sensitive::Unlock, 0, "").is_err());
+        assert!(guard.redeem(Sensitive::Unlock, 0, &token).is_ok());
+        assert!(guard.redeem(Sensitive::Unlock, 0, &token).is_err());
+    }
+}
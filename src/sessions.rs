@@ -0,0 +1,158 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Charging sessions, detected in the history snapshots.
+
+use crate::history::Snapshot;
+use serde::Serialize;
+
+/// Further apart than about 500m, it has to be another charger
+const SAME_PLACE_DEG: f64 = 0.005;
+
+/// In the units of the API: miles and kWh
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChargingSession {
+    pub vin: String,
+    pub start: i64,
+    pub end: i64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub start_soc: i64,
+    pub end_soc: i64,
+    pub energy_added: f64,
+    pub peak_charge_rate: f64,
+    pub charger_type: String,
+}
+
+impl ChargingSession {
+    fn start(s: &Snapshot) -> Self {
+        ChargingSession {
+            vin: s.vin.clone(),
+            start: s.timestamp,
+            end: s.timestamp,
+            latitude: s.latitude,
+            longitude: s.longitude,
+            start_soc: s.battery_level,
+            end_soc: s.battery_level,
+            energy_added: s.charge_energy_added,
+            peak_charge_rate: s.charge_rate,
+            charger_type: s.charger_type.clone(),
+        }
+    }
+
+    fn extend(&mut self, s: &Snapshot) {
+        self.end = s.timestamp;
+        self.end_soc = s.battery_level;
+        self.energy_added = self.energy_added.max(s.charge_energy_added);
+        self.peak_charge_rate = self.peak_charge_rate.max(s.charge_rate);
+        if self.charger_type.is_empty() {
+            self.charger_type = s.charger_type.clone();
+        }
+    }
+
+    /// The car resets charge_energy_added when it is plugged in again
+    fn continued_by(&self, s: &Snapshot) -> bool {
+        let same_place = match (self.latitude, self.longitude, s.latitude, s.longitude) {
+            (Some(lat0), Some(lon0), Some(lat1), Some(lon1)) => {
+                (lat0 - lat1).abs() < SAME_PLACE_DEG && (lon0 - lon1).abs() < SAME_PLACE_DEG
+            }
+            _ => true,
+        };
+        same_place && s.charge_energy_added >= self.energy_added
+    }
+}
+
+/// Finished sessions in the snapshots of one vehicle, oldest first.
+/// Snapshots only exist while the app was running, so a session is the run of
+/// "Charging" snapshots until the car reports something else or a new session starts.
+pub fn detect(snapshots: &[Snapshot]) -> Vec<ChargingSession> {
+    let mut sessions = Vec::new();
+    let mut current: Option<ChargingSession> = None;
+    for s in snapshots {
+        let charging = s.charging_state == "Charging";
+        match current.take() {
+            Some(mut session) if charging && session.continued_by(s) => {
+                session.extend(s);
+                current = Some(session);
+            }
+            Some(mut session) => {
+                // "Complete" and "Stopped" still show the final numbers of this session
+                let finished = matches!(s.charging_state.as_str(), "Complete" | "Stopped");
+                if !charging && finished && session.continued_by(s) {
+                    session.extend(s);
+                }
+                sessions.push(session);
+                if charging {
+                    current = Some(ChargingSession::start(s));
+                }
+            }
+            None if charging => current = Some(ChargingSession::start(s)),
+            None => {}
+        }
+    }
+
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: i64, state: &str, soc: i64, energy: f64, rate: f64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            vin: "A".to_string(),
+            battery_level: soc,
+            latitude: Some(47.0),
+            longitude: Some(8.0),
+            charging_state: state.to_string(),
+            charge_energy_added: energy,
+            charge_rate: rate,
+            charger_type: "IEC".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let snapshots = vec![
+            snapshot(0, "Disconnected", 30, 0.0, 0.0),
+            snapshot(10, "Charging", 31, 0.5, 20.0),
+            snapshot(20, "Charging", 40, 6.0, 25.0),
+            snapshot(30, "Complete", 50, 12.0, 0.0),
+            snapshot(40, "Disconnected", 50, 12.0, 0.0),
+            // plugged in again while the app wasn't watching
+            snapshot(50, "Charging", 52, 12.5, 10.0),
+            snapshot(60, "Charging", 55, 1.5, 11.0),
+            snapshot(70, "Stopped", 57, 3.0, 0.0),
+            // still charging, not finished yet
+            snapshot(80, "Charging", 58, 0.3, 9.0),
+        ];
+        let sessions = detect(&snapshots);
+        assert_eq!(sessions.len(), 3);
+
+        let first = &sessions[0];
+        assert_eq!((first.start, first.end), (10, 30));
+        assert_eq!((first.start_soc, first.end_soc), (31, 50));
+        assert_eq!(first.energy_added, 12.0);
+        assert_eq!(first.peak_charge_rate, 25.0);
+        assert_eq!(first.charger_type, "IEC");
+
+        assert_eq!((sessions[1].start, sessions[1].end), (50, 50));
+        assert_eq!((sessions[2].start, sessions[2].end), (60, 70));
+        assert_eq!(sessions[2].energy_added, 3.0);
+    }
+}
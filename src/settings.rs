@@ -0,0 +1,236 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use qmetaobject::*;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_CHARGE_LIMIT: i64 = 80;
+pub const DEFAULT_TEMP_C: f64 = 20.0;
+
+/// What is persisted to settings.json in the app data directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoredSettings {
+    pub vehicle_vin: String,
+    pub distance_unit: String,
+    pub temperature_unit: String,
+    pub pressure_unit: String,
+    pub range_type: String,
+    pub refresh_interval: i32,
+    pub hvac_temp: f64,
+    pub charge_limit: i64,
+    pub lock_timeout: i32,
+    pub auto_relock: i32,
+    pub sentry_on_lock: bool,
+    pub tpms_front_min: f64,
+    pub tpms_rear_min: f64,
+    pub history_days: i32,
+}
+
+impl Default for StoredSettings {
+    fn default() -> Self {
+        StoredSettings {
+            vehicle_vin: "".to_string(),
+            distance_unit: "auto".to_string(),
+            temperature_unit: "auto".to_string(),
+            pressure_unit: "auto".to_string(),
+            range_type: "auto".to_string(),
+            refresh_interval: 10,
+            hvac_temp: DEFAULT_TEMP_C,
+            charge_limit: DEFAULT_CHARGE_LIMIT,
+            lock_timeout: 5,
+            auto_relock: 0,
+            sentry_on_lock: false,
+            tpms_front_min: 2.5,
+            tpms_rear_min: 2.5,
+            history_days: 365,
+        }
+    }
+}
+
+impl StoredSettings {
+    pub fn load() -> Result<Self, String> {
+        let file = crate::app_data_path()?.join("settings.json");
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read the settings file {:?}: {}", file, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse the settings file {:?}: {}", file, e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let file = crate::app_data_path()?.join("settings.json");
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the settings: {:?}", e))?;
+        std::fs::write(&file, json)
+            .map_err(|e| format!("Failed to write the settings file {:?}: {}", file, e))
+    }
+}
+
+/// Generates the WRITE function of a property, which persists the change.
+macro_rules! setter {
+    ($setter:ident, $field:ident, $signal:ident, QString) => {
+        fn $setter(&mut self, value: QString) {
+            if self.$field != value {
+                self.$field = value;
+                self.stored.$field = self.$field.to_string();
+                self.$signal();
+                self.save();
+            }
+        }
+    };
+    ($setter:ident, $field:ident, $signal:ident, $t:ty) => {
+        fn $setter(&mut self, value: $t) {
+            if self.$field != value {
+                self.$field = value;
+                self.stored.$field = value;
+                self.$signal();
+                self.save();
+            }
+        }
+    };
+}
+
+#[derive(QObject)]
+pub struct AppSettings {
+    base: qt_base_class!(trait QObject),
+    stored: StoredSettings,
+
+    vehicle_vin: qt_property!(QString; NOTIFY vehicle_vin_changed WRITE set_vehicle_vin),
+    vehicle_vin_changed: qt_signal!(),
+    distance_unit: qt_property!(QString; NOTIFY distance_unit_changed WRITE set_distance_unit),
+    distance_unit_changed: qt_signal!(),
+    temperature_unit: qt_property!(QString; NOTIFY temperature_unit_changed WRITE set_temperature_unit),
+    temperature_unit_changed: qt_signal!(),
+    pressure_unit: qt_property!(QString; NOTIFY pressure_unit_changed WRITE set_pressure_unit),
+    pressure_unit_changed: qt_signal!(),
+    range_type: qt_property!(QString; NOTIFY range_type_changed WRITE set_range_type),
+    range_type_changed: qt_signal!(),
+    refresh_interval: qt_property!(i32; NOTIFY refresh_interval_changed WRITE set_refresh_interval),
+    refresh_interval_changed: qt_signal!(),
+    hvac_temp: qt_property!(f64; NOTIFY hvac_temp_changed WRITE set_hvac_temp),
+    hvac_temp_changed: qt_signal!(),
+    charge_limit: qt_property!(i64; NOTIFY charge_limit_changed WRITE set_charge_limit),
+    charge_limit_changed: qt_signal!(),
+    lock_timeout: qt_property!(i32; NOTIFY lock_timeout_changed WRITE set_lock_timeout),
+    lock_timeout_changed: qt_signal!(),
+    auto_relock: qt_property!(i32; NOTIFY auto_relock_changed WRITE set_auto_relock),
+    auto_relock_changed: qt_signal!(),
+    sentry_on_lock: qt_property!(bool; NOTIFY sentry_on_lock_changed WRITE set_sentry_on_lock),
+    sentry_on_lock_changed: qt_signal!(),
+    tpms_front_min: qt_property!(f64; NOTIFY tpms_front_min_changed WRITE set_tpms_front_min),
+    tpms_front_min_changed: qt_signal!(),
+    tpms_rear_min: qt_property!(f64; NOTIFY tpms_rear_min_changed WRITE set_tpms_rear_min),
+    tpms_rear_min_changed: qt_signal!(),
+    history_days: qt_property!(i32; NOTIFY history_days_changed WRITE set_history_days),
+    history_days_changed: qt_signal!(),
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        let stored = StoredSettings::load().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            StoredSettings::default()
+        });
+        AppSettings {
+            base: Default::default(),
+            vehicle_vin: stored.vehicle_vin.clone().into(),
+            vehicle_vin_changed: Default::default(),
+            distance_unit: stored.distance_unit.clone().into(),
+            distance_unit_changed: Default::default(),
+            temperature_unit: stored.temperature_unit.clone().into(),
+            temperature_unit_changed: Default::default(),
+            pressure_unit: stored.pressure_unit.clone().into(),
+            pressure_unit_changed: Default::default(),
+            range_type: stored.range_type.clone().into(),
+            range_type_changed: Default::default(),
+            refresh_interval: stored.refresh_interval,
+            refresh_interval_changed: Default::default(),
+            hvac_temp: stored.hvac_temp,
+            hvac_temp_changed: Default::default(),
+            charge_limit: stored.charge_limit,
+            charge_limit_changed: Default::default(),
+            lock_timeout: stored.lock_timeout,
+            lock_timeout_changed: Default::default(),
+            auto_relock: stored.auto_relock,
+            auto_relock_changed: Default::default(),
+            sentry_on_lock: stored.sentry_on_lock,
+            sentry_on_lock_changed: Default::default(),
+            tpms_front_min: stored.tpms_front_min,
+            tpms_front_min_changed: Default::default(),
+            tpms_rear_min: stored.tpms_rear_min,
+            tpms_rear_min_changed: Default::default(),
+            history_days: stored.history_days,
+            history_days_changed: Default::default(),
+            stored,
+        }
+    }
+}
+
+impl AppSettings {
+    setter!(set_vehicle_vin, vehicle_vin, vehicle_vin_changed, QString);
+    setter!(
+        set_distance_unit,
+        distance_unit,
+        distance_unit_changed,
+        QString
+    );
+    setter!(
+        set_temperature_unit,
+        temperature_unit,
+        temperature_unit_changed,
+        QString
+    );
+    setter!(
+        set_pressure_unit,
+        pressure_unit,
+        pressure_unit_changed,
+        QString
+    );
+    setter!(set_range_type, range_type, range_type_changed, QString);
+    setter!(
+        set_refresh_interval,
+        refresh_interval,
+        refresh_interval_changed,
+        i32
+    );
+    setter!(set_hvac_temp, hvac_temp, hvac_temp_changed, f64);
+    setter!(set_charge_limit, charge_limit, charge_limit_changed, i64);
+    setter!(set_lock_timeout, lock_timeout, lock_timeout_changed, i32);
+    setter!(set_auto_relock, auto_relock, auto_relock_changed, i32);
+    setter!(
+        set_sentry_on_lock,
+        sentry_on_lock,
+        sentry_on_lock_changed,
+        bool
+    );
+    setter!(
+        set_tpms_front_min,
+        tpms_front_min,
+        tpms_front_min_changed,
+        f64
+    );
+    setter!(set_tpms_rear_min, tpms_rear_min, tpms_rear_min_changed, f64);
+    setter!(set_history_days, history_days, history_days_changed, i32);
+
+    fn save(&self) {
+        if let Err(e) = self.stored.save() {
+            eprintln!("{}", e);
+        }
+    }
+}
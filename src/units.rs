@@ -0,0 +1,287 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * uttesla is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::constants::MILE_TO_KM;
+use serde::Serialize;
+
+const BAR_TO_PSI: f64 = 14.503774;
+
+/// The API reports distances and speeds in miles
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DistanceUnit {
+    #[default]
+    Km,
+    Mi,
+}
+
+/// The API reports temperatures in °C
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+/// The API reports tire pressures in bar
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PressureUnit {
+    #[default]
+    Bar,
+    Psi,
+    KPa,
+}
+
+impl PressureUnit {
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit.to_lowercase().as_str() {
+            "bar" => Some(Self::Bar),
+            "psi" => Some(Self::Psi),
+            "kpa" => Some(Self::KPa),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bar => "bar",
+            Self::Psi => "psi",
+            Self::KPa => "kPa",
+        }
+    }
+
+    pub fn convert_bar(&self, bar: f64) -> f64 {
+        match self {
+            Self::Bar => bar,
+            Self::Psi => bar * BAR_TO_PSI,
+            Self::KPa => bar * 100.0,
+        }
+    }
+
+    pub fn to_bar(self, pressure: f64) -> f64 {
+        match self {
+            Self::Bar => pressure,
+            Self::Psi => pressure / BAR_TO_PSI,
+            Self::KPa => pressure / 100.0,
+        }
+    }
+}
+
+impl DistanceUnit {
+    /// Understands the user setting as well as the car's gui_distance_units ("km/hr", "mi/hr")
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit.to_lowercase().as_str() {
+            "km" | "km/hr" | "km/h" => Some(Self::Km),
+            "mi" | "mi/hr" | "mph" => Some(Self::Mi),
+            _ => None,
+        }
+    }
+}
+
+impl TemperatureUnit {
+    /// Understands the user setting as well as the car's gui_temperature_units ("C", "F")
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit.to_lowercase().as_str() {
+            "c" | "°c" => Some(Self::Celsius),
+            "f" | "°f" => Some(Self::Fahrenheit),
+            _ => None,
+        }
+    }
+}
+
+/// Which of the ranges reported by the car to show
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RangeType {
+    #[default]
+    Rated,
+    Ideal,
+    Estimated,
+}
+
+impl RangeType {
+    fn parse(range_type: &str) -> Option<Self> {
+        match range_type.to_lowercase().as_str() {
+            "rated" => Some(Self::Rated),
+            "ideal" => Some(Self::Ideal),
+            "estimated" => Some(Self::Estimated),
+            _ => None,
+        }
+    }
+
+    /// The user setting wins, "auto" follows the car's gui_range_display ("Rated", "Ideal")
+    pub fn resolve(range_type: &str, car_range_display: &str) -> Self {
+        Self::parse(range_type)
+            .or_else(|| Self::parse(car_range_display))
+            .unwrap_or_default()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rated => "rated",
+            Self::Ideal => "ideal",
+            Self::Estimated => "estimated",
+        }
+    }
+
+    /// Picks from (rated, ideal, estimated)
+    pub fn select(&self, rated: f64, ideal: f64, estimated: f64) -> f64 {
+        match self {
+            Self::Rated => rated,
+            Self::Ideal => ideal,
+            Self::Estimated => estimated,
+        }
+    }
+}
+
+/// Unit names and the climate setting range for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitLabels {
+    pub distance: String,
+    pub speed: String,
+    pub temperature: String,
+    pub pressure: String,
+    pub temp_min: i64,
+    pub temp_max: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Units {
+    pub distance: DistanceUnit,
+    pub temperature: TemperatureUnit,
+    pub pressure: PressureUnit,
+}
+
+impl Units {
+    /// Manual settings win, "auto" or anything unknown follows the car's gui settings.
+    pub fn resolve(
+        distance: &str,
+        temperature: &str,
+        pressure: &str,
+        car_distance: &str,
+        car_temperature: &str,
+    ) -> Self {
+        let distance = DistanceUnit::parse(distance)
+            .or_else(|| DistanceUnit::parse(car_distance))
+            .unwrap_or_default();
+        let temperature = TemperatureUnit::parse(temperature)
+            .or_else(|| TemperatureUnit::parse(car_temperature))
+            .unwrap_or_default();
+        let pressure = PressureUnit::parse(pressure).unwrap_or(match distance {
+            DistanceUnit::Km => PressureUnit::Bar,
+            DistanceUnit::Mi => PressureUnit::Psi,
+        });
+        Units {
+            distance,
+            temperature,
+            pressure,
+        }
+    }
+
+    /// distances and speeds from the API's miles
+    pub fn distance(&self, miles: f64) -> f64 {
+        match self.distance {
+            DistanceUnit::Km => miles * MILE_TO_KM,
+            DistanceUnit::Mi => miles,
+        }
+    }
+
+    pub fn to_miles(self, distance: f64) -> f64 {
+        match self.distance {
+            DistanceUnit::Km => distance / MILE_TO_KM,
+            DistanceUnit::Mi => distance,
+        }
+    }
+
+    pub fn temperature(&self, celsius: f64) -> f64 {
+        match self.temperature {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn to_celsius(self, temperature: f64) -> f64 {
+        match self.temperature {
+            TemperatureUnit::Celsius => temperature,
+            TemperatureUnit::Fahrenheit => (temperature - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub fn pressure(&self, bar: f64) -> f64 {
+        self.pressure.convert_bar(bar)
+    }
+
+    pub fn to_bar(self, pressure: f64) -> f64 {
+        self.pressure.to_bar(pressure)
+    }
+
+    pub fn labels(&self) -> UnitLabels {
+        let (distance, speed) = match self.distance {
+            DistanceUnit::Km => ("km", "km/h"),
+            DistanceUnit::Mi => ("mi", "mph"),
+        };
+        // the car accepts climate settings from 15°C to 28°C
+        let (temperature, temp_min, temp_max) = match self.temperature {
+            TemperatureUnit::Celsius => ("°C", 15, 28),
+            TemperatureUnit::Fahrenheit => ("°F", 59, 82),
+        };
+        UnitLabels {
+            distance: distance.to_string(),
+            speed: speed.to_string(),
+            temperature: temperature.to_string(),
+            pressure: self.pressure.name().to_string(),
+            temp_min,
+            temp_max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let units = Units::resolve("auto", "auto", "auto", "mi/hr", "F");
+        assert_eq!(units.distance, DistanceUnit::Mi);
+        assert_eq!(units.temperature, TemperatureUnit::Fahrenheit);
+        assert_eq!(units.pressure, PressureUnit::Psi);
+
+        let units = Units::resolve("km", "C", "kPa", "mi/hr", "F");
+        assert_eq!(units.distance, DistanceUnit::Km);
+        assert_eq!(units.temperature, TemperatureUnit::Celsius);
+        assert_eq!(units.pressure, PressureUnit::KPa);
+    }
+
+    #[test]
+    fn test_range_type() {
+        assert_eq!(RangeType::resolve("auto", "Ideal"), RangeType::Ideal);
+        assert_eq!(
+            RangeType::resolve("estimated", "Ideal"),
+            RangeType::Estimated
+        );
+        assert_eq!(RangeType::resolve("auto", ""), RangeType::Rated);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let units = Units::resolve("km", "F", "psi", "", "");
+        assert!((units.distance(100.0) - 160.9344).abs() < 1e-9);
+        assert!((units.to_miles(units.distance(42.0)) - 42.0).abs() < 1e-9);
+        assert!((units.temperature(20.0) - 68.0).abs() < 1e-9);
+        assert!((units.to_celsius(68.0) - 20.0).abs() < 1e-9);
+        assert!((units.pressure(2.9) - 42.06).abs() < 0.01);
+        assert!((units.to_bar(units.pressure(2.9)) - 2.9).abs() < 1e-9);
+    }
+}